@@ -0,0 +1,319 @@
+use crate::{
+    cli::Arrangement,
+    display_config::{apply_monitors_config, get_current_state::{self, LayoutMode}},
+    planner::{current_config, current_layout_mode, logical_size},
+};
+
+/// Area that logical monitor occupies in a layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    fn center(&self) -> (i32, i32) {
+        (self.x * 2 + self.width, self.y * 2 + self.height)
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    /// Checks whether rectangles share a part of an edge. Touching only by corners does not
+    /// count, same as in mutter.
+    pub fn touches(&self, other: &Rect) -> bool {
+        let horizontal_overlap = self.x < other.right() && other.x < self.right();
+        let vertical_overlap = self.y < other.bottom() && other.y < self.bottom();
+        (vertical_overlap && (self.right() == other.x || other.right() == self.x))
+            || (horizontal_overlap && (self.bottom() == other.y || other.bottom() == self.y))
+    }
+
+    /// Manhattan distance between closest edges, 0 when rectangles touch or overlap.
+    fn gap(&self, other: &Rect) -> i32 {
+        let horizontal = (other.x - self.right()).max(self.x - other.right()).max(0);
+        let vertical = (other.y - self.bottom()).max(self.y - other.bottom()).max(0);
+        horizontal + vertical
+    }
+}
+
+/// Calculates areas of all logical monitors in a layout with `layout_mode`, in the same order.
+pub fn rects(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitors: &[apply_monitors_config::LogicalMonitor],
+) -> anyhow::Result<Vec<Rect>> {
+    logical_monitors.iter().map(|logical_monitor| {
+        let (width, height) = logical_size(current_state, layout_mode, logical_monitor)?;
+        Ok(Rect { x: logical_monitor.x, y: logical_monitor.y, width, height })
+    }).collect()
+}
+
+/// Finds logical monitors that can be reached from `start` through touching edges.
+pub fn reachable(rects: &[Rect], start: usize) -> Vec<bool> {
+    let mut reached = vec![false; rects.len()];
+    let mut queue = vec![start];
+    while let Some(index) = queue.pop() {
+        if index >= rects.len() || reached[index] {
+            continue;
+        }
+        reached[index] = true;
+        queue.extend((0..rects.len()).filter(|other| !reached[*other] && rects[index].touches(&rects[*other])));
+    }
+    reached
+}
+
+/// Checks whether every logical monitor can be reached from any other one through touching
+/// edges.
+pub fn is_connected(rects: &[Rect]) -> bool {
+    reachable(rects, 0).into_iter().all(|reached| reached)
+}
+
+/// Checks whether layout would be accepted by mutter as far as positions are concerned.
+fn is_packed(rects: &[Rect]) -> bool {
+    let at_origin = rects.iter().map(|rect| rect.x).min() == Some(0) && rects.iter().map(|rect| rect.y).min() == Some(0);
+    let overlapping = rects.iter().enumerate()
+        .any(|(index, rect)| rects[index + 1..].iter().any(|other| rect.overlaps(other)));
+    at_origin && !overlapping && is_connected(rects)
+}
+
+/// Repositions logical monitors so that they don't overlap and every one of them touches
+/// another, keeping their order.
+pub fn arrange(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitors: &mut [apply_monitors_config::LogicalMonitor],
+    arrangement: Arrangement,
+) -> anyhow::Result<()> {
+    let rects = rects(current_state, layout_mode, logical_monitors)?;
+    let positions = match arrangement {
+        Arrangement::Horizontal => arrange_in_line(&rects, true),
+        Arrangement::Vertical => arrange_in_line(&rects, false),
+        Arrangement::KeepRelative => arrange_relative(&rects),
+    };
+    for (logical_monitor, (x, y)) in logical_monitors.iter_mut().zip(positions) {
+        logical_monitor.x = x;
+        logical_monitor.y = y;
+    }
+    Ok(())
+}
+
+/// Repacks layout, keeping relative positions, only if mutter would reject it as is. Used
+/// by plans that change resolution, scale or rotation, since that changes sizes of logical
+/// monitors without moving their neighbours. Explicitly positioned layouts are left as is.
+pub fn fix_layout(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitors: &mut [apply_monitors_config::LogicalMonitor],
+) -> anyhow::Result<()> {
+    if logical_monitors.is_empty() || is_packed(&rects(current_state, layout_mode, logical_monitors)?) {
+        return Ok(());
+    }
+    arrange(current_state, layout_mode, logical_monitors, Arrangement::KeepRelative)
+}
+
+/// Plans current config, rearranged.
+pub fn plan_arrange(current_state: &get_current_state::Response, arrangement: Arrangement) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    let mut logical_monitors = current_config(current_state)?;
+    arrange(current_state, current_layout_mode(current_state), &mut logical_monitors, arrangement)?;
+    Ok(logical_monitors)
+}
+
+/// Places rectangles in a single row or column, aligned by top or left edge.
+fn arrange_in_line(rects: &[Rect], horizontal: bool) -> Vec<(i32, i32)> {
+    let mut order: Vec<_> = (0..rects.len()).collect();
+    if horizontal {
+        order.sort_by_key(|index| (rects[*index].x, rects[*index].y));
+    } else {
+        order.sort_by_key(|index| (rects[*index].y, rects[*index].x));
+    }
+    let mut positions = vec![(0, 0); rects.len()];
+    let mut offset = 0;
+    for index in order {
+        if horizontal {
+            positions[index] = (offset, 0);
+            offset += rects[index].width;
+        } else {
+            positions[index] = (0, offset);
+            offset += rects[index].height;
+        }
+    }
+    positions
+}
+
+/// Places every rectangle next to its closest neighbour, on the same side and with the same
+/// offset along the shared edge as before, as long as they still touch.
+fn arrange_relative(rects: &[Rect]) -> Vec<(i32, i32)> {
+    let mut order: Vec<_> = (0..rects.len()).collect();
+    order.sort_by_key(|index| (rects[*index].x + rects[*index].y, rects[*index].x));
+
+    let mut placed: Vec<(usize, Rect)> = Vec::with_capacity(rects.len());
+    for index in order {
+        let original = rects[index];
+        let anchor = placed.iter()
+            .min_by_key(|(anchor, _)| {
+                let (x, y) = original.center();
+                let (anchor_x, anchor_y) = rects[*anchor].center();
+                (original.gap(&rects[*anchor]), (x - anchor_x).abs() + (y - anchor_y).abs())
+            })
+            .copied();
+        let Some((anchor, anchor_rect)) = anchor else {
+            placed.push((index, Rect { x: 0, y: 0, ..original }));
+            continue;
+        };
+
+        let anchor_original = rects[anchor];
+        let (x, y) = original.center();
+        let (anchor_x, anchor_y) = anchor_original.center();
+        let (dx, dy) = (x - anchor_x, y - anchor_y);
+        let horizontal = (dx as f64 / (original.width + anchor_original.width) as f64).abs()
+            >= (dy as f64 / (original.height + anchor_original.height) as f64).abs();
+        let mut rect = if horizontal {
+            Rect {
+                x: if dx >= 0 { anchor_rect.right() } else { anchor_rect.x - original.width },
+                y: (anchor_rect.y + original.y - anchor_original.y).clamp(anchor_rect.y - original.height + 1, anchor_rect.bottom() - 1),
+                ..original
+            }
+        } else {
+            Rect {
+                x: (anchor_rect.x + original.x - anchor_original.x).clamp(anchor_rect.x - original.width + 1, anchor_rect.right() - 1),
+                y: if dy >= 0 { anchor_rect.bottom() } else { anchor_rect.y - original.height },
+                ..original
+            }
+        };
+        // Keep moving in the same direction past other monitors that are in the way
+        while let Some((_, other)) = placed.iter().find(|(_, other)| rect.overlaps(other)) {
+            match (horizontal, dx >= 0, dy >= 0) {
+                (true, true, _) => rect.x = other.right(),
+                (true, false, _) => rect.x = other.x - rect.width,
+                (false, _, true) => rect.y = other.bottom(),
+                (false, _, false) => rect.y = other.y - rect.height,
+            }
+        }
+        placed.push((index, rect));
+    }
+
+    // Mutter requires layout to start at the origin
+    let min_x = placed.iter().map(|(_, rect)| rect.x).min().unwrap_or_default();
+    let min_y = placed.iter().map(|(_, rect)| rect.y).min().unwrap_or_default();
+    let mut positions = vec![(0, 0); rects.len()];
+    for (index, rect) in placed {
+        positions[index] = (rect.x - min_x, rect.y - min_y);
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    fn positions(logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Vec<(i32, i32)> {
+        logical_monitors.iter().map(|logical_monitor| (logical_monitor.x, logical_monitor.y)).collect()
+    }
+
+    fn fixed(current_state: &get_current_state::Response, logical_monitors: &mut [apply_monitors_config::LogicalMonitor]) -> Vec<(i32, i32)> {
+        fix_layout(current_state, LayoutMode::Logical, logical_monitors).unwrap();
+        positions(logical_monitors)
+    }
+
+    #[test]
+    fn touching_by_corners_does_not_count() {
+        let rect = Rect { x: 0, y: 0, width: 10, height: 10 };
+        assert!(rect.touches(&Rect { x: 10, y: 5, width: 10, height: 10 }));
+        assert!(rect.touches(&Rect { x: -5, y: 10, width: 10, height: 10 }));
+        assert!(!rect.touches(&Rect { x: 10, y: 10, width: 10, height: 10 }));
+        assert!(!rect.overlaps(&Rect { x: 10, y: 0, width: 10, height: 10 }));
+        assert!(rect.overlaps(&Rect { x: 9, y: 9, width: 10, height: 10 }));
+    }
+
+    #[test]
+    fn shrinking_middle_monitor_closes_the_gap() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(1920, 1080)]), monitor("DP-2", &[(1920, 1080)]), monitor("DP-3", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(1920, 0, 1.0, &["DP-2"]), logical_monitor(3840, 0, 1.0, &["DP-3"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        logical_monitors[1].scale = 2.0;
+        assert_eq!(fixed(&current_state, &mut logical_monitors), [(0, 0), (1920, 0), (2880, 0)]);
+    }
+
+    #[test]
+    fn growing_external_monitor_pushes_laptop_below_it_down() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(3840, 2160)]), monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(0, 0, 2.0, &["DP-1"]), logical_monitor(0, 1080, 1.0, &["eDP-1"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        logical_monitors[0].scale = 1.5;
+        assert_eq!(fixed(&current_state, &mut logical_monitors), [(0, 0), (0, 1440)]);
+    }
+
+    #[test]
+    fn laptop_keeps_its_offset_below_external_monitor() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(2560, 1440)]), monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(200, 0, 1.0, &["DP-1"]), logical_monitor(0, 1540, 1.0, &["eDP-1"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        // There is a 100 pixel gap, which is closed without changing horizontal offset
+        assert_eq!(fixed(&current_state, &mut logical_monitors), [(200, 0), (0, 1440)]);
+    }
+
+    #[test]
+    fn packed_layout_is_left_as_is() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(2560, 1440)]), monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(320, 1440, 1.0, &["eDP-1"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        assert_eq!(fixed(&current_state, &mut logical_monitors), [(0, 0), (320, 1440)]);
+    }
+
+    #[test]
+    fn mirrored_displays_are_moved_together() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1080)]), monitor("HDMI-1", &[(1920, 1080)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1", "HDMI-1"]), logical_monitor(1920, 0, 1.0, &["DP-1"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        logical_monitors[0].scale = 2.0;
+        assert_eq!(fixed(&current_state, &mut logical_monitors), [(0, 0), (960, 0)]);
+        assert_eq!(logical_monitors[0].monitors.len(), 2);
+    }
+
+    #[test]
+    fn physical_layout_mode_ignores_scale() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(3840, 2160)]), monitor("DP-2", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 2.0, &["DP-1"]), logical_monitor(3840, 0, 1.0, &["DP-2"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        fix_layout(&current_state, LayoutMode::Physical, &mut logical_monitors).unwrap();
+        assert_eq!(positions(&logical_monitors), [(0, 0), (3840, 0)]);
+        fix_layout(&current_state, LayoutMode::Logical, &mut logical_monitors).unwrap();
+        assert_eq!(positions(&logical_monitors), [(0, 0), (1920, 0)]);
+    }
+
+    #[test]
+    fn arranging_in_a_row_keeps_order() {
+        let current_state = state(
+            vec![monitor("DP-1", &[(1920, 1080)]), monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(320, 0, 1.0, &["DP-1"]), logical_monitor(0, 1080, 1.0, &["eDP-1"])],
+        );
+        let horizontal = plan_arrange(&current_state, Arrangement::Horizontal).unwrap();
+        assert_eq!(positions(&horizontal), [(1920, 0), (0, 0)]);
+        let vertical = plan_arrange(&current_state, Arrangement::Vertical).unwrap();
+        assert_eq!(positions(&vertical), [(0, 0), (0, 1080)]);
+    }
+}
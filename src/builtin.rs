@@ -0,0 +1,176 @@
+use anyhow::anyhow;
+
+use crate::{
+    arrange::fix_layout,
+    cli::{BuiltinPolicy, SetArgs},
+    display_config::{apply_monitors_config, get_current_state},
+    gsettings,
+    planner::{current_config, current_layout_mode, plan_set},
+};
+
+/// GSettings schema where GNOME keeps rotation lock of accelerometer based auto-rotation.
+const TOUCHSCREEN_SCHEMA: &str = "org.gnome.settings-daemon.peripherals.touchscreen";
+
+#[zbus::proxy(
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1",
+    interface = "org.freedesktop.login1.Manager"
+)]
+pub trait Login1Manager {
+    /// LidClosed property. logind doesn't emit change signals for it, so it's read every time
+    /// instead of being cached
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn lid_closed(&self) -> zbus::Result<bool>;
+}
+
+/// Connects to logind on system bus, which reports lid state. Returns `None` if logind is not
+/// reachable, e.g. inside of a container.
+pub async fn login1_proxy() -> Option<Login1ManagerProxy<'static>> {
+    let conn = zbus::Connection::system().await.ok()?;
+    Login1ManagerProxy::new(&conn).await.ok()
+}
+
+/// Finds a built-in panel, e.g. laptop screen, among connected displays.
+pub fn builtin_monitor(current_state: &get_current_state::Response) -> anyhow::Result<&get_current_state::Monitor> {
+    current_state.monitors.iter()
+        .find(|monitor| monitor.properties.is_builtin.unwrap_or(false))
+        .ok_or(anyhow!("there is no built-in display connected"))
+}
+
+/// Whether display could take over from built-in panel. Displays that are leased, e.g. VR
+/// headsets, are not part of the desktop.
+fn usable_external(monitor: &get_current_state::Monitor) -> bool {
+    !monitor.properties.is_builtin.unwrap_or(false) && !monitor.properties.is_for_lease.unwrap_or(false)
+}
+
+/// Decides whether built-in panel should be enabled according to the policy.
+pub fn builtin_wanted(current_state: &get_current_state::Response, policy: BuiltinPolicy, lid_closed: Option<bool>) -> anyhow::Result<bool> {
+    let has_external = current_state.monitors.iter().any(usable_external);
+    match policy {
+        BuiltinPolicy::On => Ok(true),
+        BuiltinPolicy::Off => Ok(false),
+        BuiltinPolicy::OffWhenExternal => Ok(!has_external),
+        BuiltinPolicy::OffWhenLidClosed => {
+            let lid_closed = lid_closed.ok_or(anyhow!("lid state is not available from logind"))?;
+            Ok(!(lid_closed && has_external))
+        },
+    }
+}
+
+/// Checks whether built-in panel is currently part of the layout.
+pub fn builtin_enabled(current_state: &get_current_state::Response) -> anyhow::Result<bool> {
+    let builtin = builtin_monitor(current_state)?;
+    Ok(current_state.logical_monitors.iter().any(|logical_monitor| logical_monitor.monitors.contains(&builtin.id)))
+}
+
+/// Plans a layout with built-in panel enabled or disabled. When disabling it, first external
+/// display takes over as primary, and it's enabled if it wasn't already.
+pub fn plan_builtin(current_state: &get_current_state::Response, enable: bool) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    let builtin = builtin_monitor(current_state)?;
+    if enable {
+        return plan_set(current_state, &SetArgs {
+            connector: Some(builtin.id.connector.clone()),
+            auto: true,
+            ..Default::default()
+        });
+    }
+
+    let has_enabled_external = current_state.logical_monitors.iter()
+        .any(|logical_monitor| logical_monitor.monitors.iter().any(|monitor_id| monitor_id != &builtin.id));
+    let mut logical_monitors = if has_enabled_external {
+        current_config(current_state)?
+    } else {
+        let external = current_state.monitors.iter()
+            .find(|monitor| usable_external(monitor))
+            .ok_or(anyhow!("built-in display \"{}\" is the only connected display that is not leased", builtin.id.connector))?;
+        plan_set(current_state, &SetArgs {
+            connector: Some(external.id.connector.clone()),
+            auto: true,
+            ..Default::default()
+        })?
+    };
+    logical_monitors.retain(|logical_monitor| logical_monitor.monitors.iter().all(|monitor| monitor.connector != builtin.id.connector));
+
+    fix_layout(current_state, current_layout_mode(current_state), &mut logical_monitors)?;
+    if !logical_monitors.iter().any(|logical_monitor| logical_monitor.primary) {
+        if let Some(first) = logical_monitors.iter_mut().min_by_key(|logical_monitor| (logical_monitor.x, logical_monitor.y)) {
+            first.primary = true;
+        }
+    }
+    Ok(logical_monitors)
+}
+
+/// Whether GNOME auto-rotation is locked, i.e. built-in display keeps its rotation.
+pub async fn rotation_locked() -> anyhow::Result<bool> {
+    gsettings::get(TOUCHSCREEN_SCHEMA, "orientation-lock").await
+}
+
+pub async fn set_rotation_locked(locked: bool) -> anyhow::Result<()> {
+    gsettings::set(TOUCHSCREEN_SCHEMA, "orientation-lock", &locked.to_string()).await
+}
+
+/// Finds a built-in display whose rotation would be changed by `logical_monitors`.
+pub fn rotated_builtin(current_state: &get_current_state::Response, logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Option<String> {
+    let builtin = builtin_monitor(current_state).ok()?;
+    let current_transform = current_state.logical_monitors.iter()
+        .find(|logical_monitor| logical_monitor.monitors.contains(&builtin.id))
+        .map(|logical_monitor| logical_monitor.transform);
+    let planned_transform = logical_monitors.iter()
+        .find(|logical_monitor| logical_monitor.monitors.iter().any(|monitor| monitor.connector == builtin.id.connector))
+        .map(|logical_monitor| logical_monitor.transform);
+    match (current_transform, planned_transform) {
+        (Some(current), Some(planned)) if current != planned => Some(builtin.id.connector.clone()),
+        _ => None,
+    }
+}
+
+/// Wraps `plan`, so that it fails if rotation of built-in display is changed while mutter
+/// manages its orientation, as auto-rotation would revert it as soon as device moves.
+pub fn with_rotation_check<F>(orientation_managed: bool, plan: F) -> impl Fn(&get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>>
+where
+    F: Fn(&get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>>,
+{
+    move |current_state| {
+        let logical_monitors = plan(current_state)?;
+        if let (true, Some(connector)) = (orientation_managed, rotated_builtin(current_state, &logical_monitors)) {
+            return Err(anyhow!(
+                "orientation of built-in display \"{connector}\" is managed by auto-rotation, which would revert this change, use `rotation-lock on` first or pass --force"
+            ));
+        }
+        Ok(logical_monitors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    /// Laptop with a VR headset, which is leased instead of being part of the desktop
+    fn laptop_with_headset() -> get_current_state::Response {
+        let mut headset = monitor("DP-1", &[(2160, 2160)]);
+        headset.properties.is_for_lease = Some(true);
+        state(vec![monitor("eDP-1", &[(1920, 1200)]), headset], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])])
+    }
+
+    #[test]
+    fn leased_displays_are_not_external() {
+        let current_state = laptop_with_headset();
+        assert!(builtin_wanted(&current_state, BuiltinPolicy::OffWhenExternal, None).unwrap());
+        assert!(builtin_wanted(&current_state, BuiltinPolicy::OffWhenLidClosed, Some(true)).unwrap());
+        let err = plan_builtin(&current_state, false).unwrap_err();
+        assert_eq!(err.to_string(), "built-in display \"eDP-1\" is the only connected display that is not leased");
+    }
+
+    #[test]
+    fn external_display_takes_over_when_builtin_is_disabled() {
+        let mut current_state = laptop_with_headset();
+        current_state.monitors.push(monitor("HDMI-1", &[(1920, 1080)]));
+        assert!(!builtin_wanted(&current_state, BuiltinPolicy::OffWhenExternal, None).unwrap());
+        let logical_monitors = plan_builtin(&current_state, false).unwrap();
+        assert_eq!(logical_monitors.len(), 1);
+        assert_eq!(logical_monitors[0].monitors[0].connector, "HDMI-1");
+        assert!(logical_monitors[0].primary);
+        assert_eq!((logical_monitors[0].x, logical_monitors[0].y), (0, 0));
+    }
+}
@@ -1,4 +1,11 @@
-use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "dynamic-completion")]
+use clap_complete::engine::ArgValueCompleter;
+
+#[cfg(feature = "dynamic-completion")]
+use crate::completion::{complete_connector, complete_mode, complete_refresh_rate, complete_resolution};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -13,56 +20,395 @@ pub enum Command {
     List(ListArgs),
     /// Set config
     Set(SetArgs),
+    /// Show display configuration status reported by mutter
+    Status,
+    /// Watch for monitor changes and print them as they happen
+    Watch(WatchArgs),
+    /// Apply config using xrandr command line syntax
+    ///
+    /// Supports --output, --mode, --rate, --pos, --rotate, --primary, --off, --auto, --left-of,
+    /// --right-of, --above, --below, --scale and --query. Same mode is used when binary is invoked
+    /// through a symlink named xrandr
+    Xrandr(XrandrArgs),
+    /// Import, export and validate mutter's monitors.xml
+    #[command(subcommand)]
+    MonitorsXml(MonitorsXmlCommand),
+    /// Reposition all enabled displays, so that there are no gaps or overlaps between them
+    Arrange(ArrangeArgs),
+    /// Restore configuration that was active before one of the recent changes. Restored
+    /// configuration and the ones after it are removed from history
+    Undo(UndoArgs),
+    /// List configurations that could be restored with `undo`
+    History,
+    /// Lock or unlock auto-rotation of built-in display. Shows current state if not specified
+    RotationLock(RotationLockArgs),
+    /// Control night light, which makes colors warmer at night
+    #[command(subcommand)]
+    NightLight(NightLightCommand),
+    /// Arrange displays and change their settings interactively
+    Tui,
+    /// Save an image of display arrangement, e.g. for documentation or support tickets
+    ExportLayout(ExportLayoutArgs),
+    /// Enable or disable built-in display, e.g. laptop panel, depending on connected displays
+    Builtin(BuiltinArgs),
+    /// Export D-Bus service for other applications, which provides higher level operations
+    /// than mutter itself
+    Serve,
+    /// Print shell completion script
+    ///
+    /// Completion of connectors, resolutions and refresh rates is based on currently connected
+    /// displays
+    Completions(CompletionsArgs),
 }
 
 #[derive(Debug, Args)]
 pub struct ListArgs {
     /// If specified - will list all available modes for a monitor with matching connector name
     #[arg(short, long)]
+    #[cfg_attr(feature = "dynamic-completion", arg(add = ArgValueCompleter::new(complete_connector)))]
     pub connector: Option<String>,
+    /// Draw arrangement of enabled displays instead
+    #[arg(long, conflicts_with = "connector")]
+    pub layout: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RotationLockArgs {
+    pub state: Option<Switch>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Switch {
+    On,
+    Off,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NightLightCommand {
+    /// Show night light settings and whether it's active right now
+    Status,
+    /// Enable night light
+    On,
+    /// Disable night light
+    Off,
+    /// Set color temperature that is used while night light is active
+    Temperature(NightLightTemperatureArgs),
+    /// Set when night light is active
+    Schedule(NightLightScheduleArgs),
 }
 
 #[derive(Debug, Args)]
+pub struct NightLightTemperatureArgs {
+    /// Color temperature in Kelvin, from 1700 (warmest) to 4700
+    pub kelvin: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct NightLightScheduleArgs {
+    /// Start time, e.g. 20:00. Night light follows sunset and sunrise if times are not specified
+    #[arg(long, value_parser = time_parser, requires = "to")]
+    pub from: Option<f64>,
+    /// End time, e.g. 06:30
+    #[arg(long, value_parser = time_parser, requires = "from")]
+    pub to: Option<f64>,
+}
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {
+    /// How many changes to go back, as numbered by `history`
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub steps: u32,
+    /// Change rotation of built-in display even if it's managed by auto-rotation
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ArrangeArgs {
+    /// Put displays in a row or column in their current order, or place each display next to its
+    /// closest neighbour on the same side as before
+    pub arrangement: Arrangement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Arrangement {
+    Horizontal,
+    Vertical,
+    KeepRelative,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportLayoutArgs {
+    /// Write SVG image to this file
+    #[arg(long, required_unless_present = "save_state")]
+    pub svg: Option<PathBuf>,
+    /// Draw physical sizes reported by displays instead of logical arrangement
+    #[arg(long, requires = "svg")]
+    pub physical: bool,
+    /// Read display state saved with --save-state instead of querying mutter
+    #[arg(long, conflicts_with = "save_state")]
+    pub state: Option<PathBuf>,
+    /// Save current display state to this file, so it could be rendered elsewhere with --state
+    #[arg(long)]
+    pub save_state: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct BuiltinArgs {
+    /// When built-in display should be enabled. If it's disabled, first external display becomes
+    /// primary
+    pub policy: BuiltinPolicy,
+    /// Keep running and re-apply the policy whenever mutter reports display changes, which
+    /// includes plugging or unplugging displays and opening or closing the lid
+    #[arg(long)]
+    pub watch: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BuiltinPolicy {
+    On,
+    Off,
+    OffWhenExternal,
+    OffWhenLidClosed,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MonitorsXmlCommand {
+    /// Export current configuration in monitors.xml format
+    Export(MonitorsXmlExportArgs),
+    /// Apply configuration from monitors.xml
+    Import(MonitorsXmlImportArgs),
+    /// Check monitors.xml for errors, including compatibility with connected displays
+    Validate(MonitorsXmlValidateArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct MonitorsXmlExportArgs {
+    /// File to write to. Prints to stdout if not specified
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Write to a profile with this name instead, which could be applied by `serve` mode
+    #[arg(long, conflicts_with = "output")]
+    pub profile: Option<String>,
+    /// Keep other configurations in existing output file, only replacing one for currently
+    /// connected displays
+    #[arg(long)]
+    pub merge: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct MonitorsXmlImportArgs {
+    /// Path to monitors.xml, defaults to ~/.config/monitors.xml
+    pub file: Option<PathBuf>,
+    /// Read a profile with this name instead
+    #[arg(long, conflicts_with = "file")]
+    pub profile: Option<String>,
+    /// Number of configuration to apply, as shown by `monitors-xml validate`. Defaults to the
+    /// one that matches currently connected displays
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub configuration: Option<u32>,
+    /// Save config to the disk after applying it. Will prompt for user input to verify if it's
+    /// correct
+    #[arg(short, long)]
+    pub persistent: bool,
+    /// Change rotation of built-in display even if it's managed by auto-rotation
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct MonitorsXmlValidateArgs {
+    /// Path to monitors.xml, defaults to ~/.config/monitors.xml
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct XrandrArgs {
+    /// Change rotation of built-in display even if it's managed by auto-rotation. Has to come
+    /// before xrandr arguments
+    #[arg(long)]
+    pub force: bool,
+    /// xrandr arguments, e.g. --output DP-1 --mode 1920x1080 --rate 60 --primary
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate completion script for
+    pub shell: Shell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Print each change event as a single line of JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Default, Args)]
+#[command(group(ArgGroup::new("target").required(true).args(["connector", "all", "monitor"])))]
 pub struct SetArgs {
     /// Name of monitor connector, e.g. DP-1, HDMI-2
     #[arg(short, long)]
-    pub connector: String,
+    #[cfg_attr(feature = "dynamic-completion", arg(add = ArgValueCompleter::new(complete_connector)))]
+    pub connector: Option<String>,
+    /// Apply the same changes to every enabled display, e.g. `--all --max-refresh-rate`. Best
+    /// mode is selected for each display independently
+    #[arg(long)]
+    pub all: bool,
+    /// Apply the same changes to every enabled display of this kind
+    #[arg(long)]
+    pub monitor: Option<MonitorSelector>,
     /// Save config to the disk after applying it. Will prompt for user input to verify if it's
     /// correct
     #[arg(short, long)]
     pub persistent: bool,
+    /// Exact mode ID, as shown by `list --connector`, e.g. 1920x1080@59.940. Takes precedence
+    /// over resolution, refresh rate and VRR selection
+    #[arg(short, long, conflicts_with_all = ["resolution", "max_resolution", "refresh_rate", "max_refresh_rate", "min_refresh_rate", "vrr"])]
+    #[cfg_attr(feature = "dynamic-completion", arg(add = ArgValueCompleter::new(complete_mode)))]
+    pub mode: Option<String>,
+    /// Use preferred mode of the display and its preferred scale. Its variable or fixed refresh
+    /// rate counterpart is picked the same way as with `--vrr` for other modes
+    #[arg(long, conflicts_with_all = ["mode", "resolution", "max_resolution", "refresh_rate", "max_refresh_rate", "min_refresh_rate"])]
+    pub preferred: bool,
+    /// Enable the display if it's disabled, using its preferred mode and scale unless other
+    /// values are requested. Has no effect on displays that are already enabled
+    #[arg(long)]
+    pub auto: bool,
     /// New resolution, e.g. 1920x1080, 3840x2160
     #[arg(short, long, group = "res", value_parser = resolution_parser)]
+    #[cfg_attr(feature = "dynamic-completion", arg(add = ArgValueCompleter::new(complete_resolution)))]
     pub resolution: Option<(u32, u32)>,
-    /// Automatically select highest available refresh rate
-    #[arg(long, group = "res")]
-    pub max_resolution: bool,
+    /// Automatically select highest available resolution, either by total amount of pixels
+    /// (default) or by width
+    #[arg(long, group = "res", num_args = 0..=1, require_equals = true, default_missing_value = "pixels")]
+    pub max_resolution: Option<MaxResolution>,
+    /// Only consider resolutions with this aspect ratio when selecting highest resolution, e.g.
+    /// 16:9, 21:9
+    #[arg(long, requires = "max_resolution", value_parser = aspect_parser)]
+    pub aspect: Option<(u32, u32)>,
     /// New monitor refresh rate. This is selected on a best effort basis. e.g. if you
     /// select 60Hz, while monitor only supports 59.98Hz, it will be selected instead.
     #[arg(long, group = "refresh")]
+    #[cfg_attr(feature = "dynamic-completion", arg(add = ArgValueCompleter::new(complete_refresh_rate)))]
     pub refresh_rate: Option<f64>,
     /// Automatically select highest refresh rate for selected resolution
     #[arg(long, group = "refresh")]
     pub max_refresh_rate: bool,
-    /// Controls variable refresh rate
+    /// Only consider modes with at least this refresh rate. Rates that are less than 0.5Hz
+    /// lower are accepted as well, e.g. 59.94Hz satisfies 60
+    #[arg(long)]
+    pub min_refresh_rate: Option<f64>,
+    /// Controls variable refresh rate. Switches to a counterpart of selected mode with variable or
+    /// fixed refresh rate. If not specified - current refresh rate mode is kept when possible
     #[arg(long)]
     pub vrr: Option<bool>,
     /// UI Scaling, as precentage, e.g. 100, 150, 200. This is selected based on a closest
     /// available scaling with a rounding step of 25%. e.g. if you select 125, while selected
     /// resolution only allows for either 124% or 149% - first one will be selected.
-    #[arg(long)]
-    pub scaling: Option<u32>,
+    /// Alternatively "auto-dpi[=<target>]" selects a scale that brings effective DPI of the
+    /// display closest to target, which is 96 by default
+    #[arg(long, group = "scale_value", value_parser = scaling_parser)]
+    pub scaling: Option<Scaling>,
+    /// Exact UI scale factor, e.g. 1.3333, 1.75. Closest supported scale is selected, as long as
+    /// it's within --scale-tolerance
+    #[arg(long, group = "scale_value")]
+    pub scale: Option<f64>,
+    /// Maximum allowed difference between requested and selected scale factor. Defaults to 0.01
+    /// for --scale, --scaling is rounded to a closest 25% step instead
+    #[arg(long, requires = "scale_value")]
+    pub scale_tolerance: Option<f64>,
     /// Controls high dynamic range color mode
     #[arg(long)]
     pub hdr: Option<bool>,
+    /// Wait until mutter reports that new config took effect. Exits with an error if display
+    /// ended up in a different configuration
+    #[arg(long)]
+    pub wait: bool,
+    /// How long to wait for new config to take effect, in seconds
+    #[arg(long, requires = "wait", default_value_t = 10)]
+    pub wait_timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MonitorSelector {
+    Builtin,
+    External,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MaxResolution {
+    Pixels,
+    Width,
+}
+
+/// Effective DPI that is targeted by `--scaling auto-dpi` when no explicit target is given
+pub const DEFAULT_TARGET_DPI: f64 = 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scaling {
+    /// Scale as a percentage
+    Percent(u32),
+    /// Scale that results in an effective DPI closest to the target
+    AutoDpi(f64),
+}
+
+fn scaling_parser(s: &str) -> Result<Scaling, String> {
+    match s.strip_prefix("auto-dpi") {
+        Some("") => Ok(Scaling::AutoDpi(DEFAULT_TARGET_DPI)),
+        Some(target) => {
+            let target = target.strip_prefix('=')
+                .and_then(|target| target.parse::<f64>().ok())
+                .filter(|target| *target > 0.0)
+                .ok_or("could not parse target DPI, expected format is auto-dpi=<target>, e.g. auto-dpi=110".to_string())?;
+            Ok(Scaling::AutoDpi(target))
+        },
+        None => s.parse::<u32>()
+            .map(Scaling::Percent)
+            .map_err(|_| "could not parse scaling, expected either percentage or auto-dpi[=<target>]".to_string()),
+    }
 }
 
 fn resolution_parser(s: &str) -> Result<(u32, u32), String> {
     let res: Vec<_> = s.split(&['x', 'X']).map(str::parse::<u32>).collect();
     if res.len() != 2 {
-        return Err(format!("could not parse resolution string, expected format is <widht>x<height>, e.g. 1920x1080"));
+        return Err("could not parse resolution string, expected format is <widht>x<height>, e.g. 1920x1080".to_string());
     }
-    let width = res[0].as_ref().map_err(|_| format!("could not parse resolution, width is not a number"))?;
-    let height = res[1].as_ref().map_err(|_| format!("could not parse resolution, height is not a number"))?;
+    let width = res[0].as_ref().map_err(|_| "could not parse resolution, width is not a number".to_string())?;
+    let height = res[1].as_ref().map_err(|_| "could not parse resolution, height is not a number".to_string())?;
     Ok((*width, *height))
 }
+
+fn aspect_parser(s: &str) -> Result<(u32, u32), String> {
+    let aspect: Vec<_> = s.split(':').map(str::parse::<u32>).collect();
+    if aspect.len() != 2 {
+        return Err("could not parse aspect ratio, expected format is <width>:<height>, e.g. 16:9".to_string());
+    }
+    let width = aspect[0].as_ref().map_err(|_| "could not parse aspect ratio, width is not a number".to_string())?;
+    let height = aspect[1].as_ref().map_err(|_| "could not parse aspect ratio, height is not a number".to_string())?;
+    if *width == 0 || *height == 0 {
+        return Err("could not parse aspect ratio, width and height should be positive".to_string());
+    }
+    Ok((*width, *height))
+}
+
+/// Parses time of day into hours, e.g. 20:30 into 20.5
+fn time_parser(s: &str) -> Result<f64, String> {
+    let (hours, minutes) = s.split_once(':')
+        .ok_or("could not parse time, expected format is <hours>:<minutes>, e.g. 20:30".to_string())?;
+    let hours: u32 = hours.parse().map_err(|_| "could not parse time, hours is not a number".to_string())?;
+    let minutes: u32 = minutes.parse().map_err(|_| "could not parse time, minutes is not a number".to_string())?;
+    if hours >= 24 || minutes >= 60 {
+        return Err("could not parse time, it should be between 00:00 and 23:59".to_string());
+    }
+    Ok(hours as f64 + minutes as f64 / 60.0)
+}
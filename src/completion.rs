@@ -0,0 +1,115 @@
+use std::ffi::OsStr;
+
+use clap_complete::engine::CompletionCandidate;
+
+use crate::display_config::{get_current_state, DisplayConfigProxy};
+
+/// Completes connector names of currently connected displays
+pub fn complete_connector(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current_state) = fetch_current_state() else {
+        return Vec::new();
+    };
+    let current = current.to_string_lossy();
+    current_state.monitors.iter()
+        .filter(|monitor| monitor.id.connector.starts_with(current.as_ref()))
+        .map(|monitor| {
+            let name = monitor.properties.display_name.clone()
+                .unwrap_or(format!("{} {}", monitor.id.vendor, monitor.id.product));
+            CompletionCandidate::new(&monitor.id.connector).help(Some(name.into()))
+        })
+        .collect()
+}
+
+/// Completes resolutions that are available for a display passed in `--connector`, or for any
+/// display if it was not passed yet
+pub fn complete_resolution(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current_state) = fetch_current_state() else {
+        return Vec::new();
+    };
+    let current = current.to_string_lossy();
+    let mut modes = completed_modes(&current_state);
+    modes.sort();
+    modes.reverse();
+    let mut resolutions: Vec<String> = Vec::new();
+    for mode in modes {
+        let resolution = format!("{}x{}", mode.width, mode.height);
+        if resolution.starts_with(current.as_ref()) && !resolutions.contains(&resolution) {
+            resolutions.push(resolution);
+        }
+    }
+    resolutions.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// Completes refresh rates that are available for a resolution passed in `--resolution`, or
+/// for current resolution if it was not passed yet
+pub fn complete_refresh_rate(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current_state) = fetch_current_state() else {
+        return Vec::new();
+    };
+    let current = current.to_string_lossy();
+    let modes = completed_modes(&current_state);
+    let resolution = arg_value(&["-r", "--resolution"]).or_else(|| {
+        modes.iter()
+            .find(|mode| mode.properties.is_current.unwrap_or(false))
+            .map(|mode| format!("{}x{}", mode.width, mode.height))
+    });
+    let mut refresh_rates: Vec<_> = modes.iter()
+        .filter(|mode| resolution.as_ref().is_none_or(|resolution| resolution == &format!("{}x{}", mode.width, mode.height)))
+        .map(|mode| mode.refresh_rate)
+        .collect();
+    refresh_rates.sort_by(|a, b| b.total_cmp(a));
+    refresh_rates.dedup();
+    refresh_rates.into_iter()
+        .map(|refresh_rate| format!("{refresh_rate:.3}"))
+        .filter(|refresh_rate| refresh_rate.starts_with(current.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Completes mode IDs that are available for a display passed in `--connector`
+pub fn complete_mode(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current_state) = fetch_current_state() else {
+        return Vec::new();
+    };
+    let current = current.to_string_lossy();
+    completed_modes(&current_state).into_iter()
+        .filter(|mode| mode.id.starts_with(current.as_ref()))
+        .map(|mode| CompletionCandidate::new(mode.id))
+        .collect()
+}
+
+/// Modes of a display passed in `--connector`, or of all displays if it was not passed yet
+fn completed_modes(current_state: &get_current_state::Response) -> Vec<get_current_state::Mode> {
+    let connector = arg_value(&["-c", "--connector"]);
+    current_state.monitors.iter()
+        .filter(|monitor| connector.as_ref().is_none_or(|connector| &monitor.id.connector == connector))
+        .flat_map(|monitor| monitor.modes.clone())
+        .collect()
+}
+
+/// Completers only receive a value that is being completed, so values of other arguments are
+/// looked up in a command line that was passed by the shell
+fn arg_value(names: &[&str]) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut value = None;
+    for (i, arg) in args.iter().enumerate() {
+        for name in names {
+            if arg == name {
+                value = args.get(i + 1).cloned();
+            } else if let Some(inline) = arg.strip_prefix(&format!("{name}=")) {
+                value = Some(inline.to_string());
+            }
+        }
+    }
+    value
+}
+
+/// Completion is performed before async runtime is started, so it uses its own
+fn fetch_current_state() -> Option<get_current_state::Response> {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().ok()?;
+    runtime.block_on(async {
+        let conn = zbus::Connection::session().await.ok()?;
+        let proxy = DisplayConfigProxy::new(&conn).await.ok()?;
+        proxy.get_current_state().await.ok()
+    })
+}
@@ -0,0 +1,273 @@
+use anyhow::anyhow;
+
+use crate::{
+    display_config::get_current_state::{self, LogicalMonitorTransform},
+    planner::{current_config, current_layout_mode, logical_size},
+};
+
+/// Minimal amount of rows a box takes, so that labels fit inside of it.
+const MIN_BOX_ROWS: f64 = 5.0;
+
+/// Terminal cells are roughly twice as tall as they are wide.
+pub const CELL_ASPECT_RATIO: f64 = 2.0;
+
+/// Position and size of a logical monitor in layout coordinates, along with details that are
+/// shown in diagrams.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// more than one for mirrored displays
+    pub connectors: Vec<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub mode_width: i32,
+    pub mode_height: i32,
+    pub scale: f64,
+    pub transform: LogicalMonitorTransform,
+    pub primary: bool,
+}
+
+impl Placement {
+    /// Connectors joined by "+", e.g. "eDP-1+HDMI-1" for mirrored displays
+    fn name(&self) -> String {
+        self.connectors.join("+")
+    }
+
+    fn labels(&self) -> Vec<String> {
+        let mut labels = vec![
+            if self.primary { format!("{} (primary)", self.name()) } else { self.name() },
+            format!("{}x{}", self.mode_width, self.mode_height),
+        ];
+        if self.scale != 1.0 {
+            labels.push(format!("scale {:.2}", self.scale));
+        }
+        if self.transform != LogicalMonitorTransform::Normal {
+            labels.push(transform_label(self.transform).to_string());
+        }
+        labels
+    }
+}
+
+/// Short human readable description of transform, empty for normal orientation.
+pub fn transform_label(transform: LogicalMonitorTransform) -> &'static str {
+    match transform {
+        LogicalMonitorTransform::Normal => "",
+        LogicalMonitorTransform::Turned90Deg => "rotated 90°",
+        LogicalMonitorTransform::Turned180Deg => "rotated 180°",
+        LogicalMonitorTransform::Turned270Deg => "rotated 270°",
+        LogicalMonitorTransform::Flipped => "flipped",
+        LogicalMonitorTransform::Turned90DegFlipped => "flipped, rotated 90°",
+        LogicalMonitorTransform::Turned180DegFlipped => "flipped, rotated 180°",
+        LogicalMonitorTransform::Turned270DegFlipped => "flipped, rotated 270°",
+    }
+}
+
+/// Calculates where every logical monitor is placed. Mirrored displays share a single entry,
+/// since they use the same resolution.
+pub fn placements(current_state: &get_current_state::Response) -> anyhow::Result<Vec<Placement>> {
+    current_config(current_state)?.into_iter().map(|logical_monitor| {
+        let (width, height) = logical_size(current_state, current_layout_mode(current_state), &logical_monitor)?;
+        let monitor = logical_monitor.monitors.first().ok_or(anyhow!("logical monitor does not have any displays attached"))?;
+        let mode = current_state.monitors.iter()
+            .find(|m| m.id.connector == monitor.connector)
+            .and_then(|m| m.modes.iter().find(|mode| mode.id == monitor.mode))
+            .ok_or(anyhow!("display \"{}\" does not have a mode with \"{}\" ID", monitor.connector, monitor.mode))?;
+        Ok(Placement {
+            connectors: logical_monitor.monitors.iter().map(|monitor| monitor.connector.clone()).collect(),
+            x: logical_monitor.x,
+            y: logical_monitor.y,
+            width,
+            height,
+            mode_width: mode.width,
+            mode_height: mode.height,
+            scale: logical_monitor.scale,
+            transform: logical_monitor.transform,
+            primary: logical_monitor.primary,
+        })
+    }).collect()
+}
+
+/// Draws logical monitors as boxes made of box-drawing characters, scaled to fit into
+/// `columns` terminal cells.
+pub fn render_text(current_state: &get_current_state::Response, columns: usize) -> anyhow::Result<String> {
+    let placements = placements(current_state)?;
+    if placements.is_empty() {
+        return Ok("No enabled displays\n".to_string());
+    }
+    let total_width = placements.iter().map(|p| p.x + p.width).max().unwrap_or_default().max(1);
+    let total_height = placements.iter().map(|p| p.y + p.height).max().unwrap_or_default().max(1);
+    let min_height = placements.iter().map(|p| p.height).min().unwrap_or_default().max(1);
+
+    let scale_x = columns.max(1) as f64 / total_width as f64;
+    let scale_y = (scale_x / CELL_ASPECT_RATIO).max(MIN_BOX_ROWS / min_height as f64);
+    let rows = (total_height as f64 * scale_y).round() as usize;
+    let mut grid = vec![vec![' '; columns]; rows];
+
+    for placement in &placements {
+        let left = (placement.x as f64 * scale_x).round() as usize;
+        let right = (((placement.x + placement.width) as f64 * scale_x).round() as usize).clamp(left + 1, columns) - 1;
+        let top = (placement.y as f64 * scale_y).round() as usize;
+        let bottom = (((placement.y + placement.height) as f64 * scale_y).round() as usize).clamp(top + 1, rows) - 1;
+        if left >= right || top >= bottom {
+            continue;
+        }
+
+        grid[top][left + 1..right].fill('─');
+        grid[bottom][left + 1..right].fill('─');
+        for row in grid.iter_mut().take(bottom).skip(top + 1) {
+            row[left] = '│';
+            row[right] = '│';
+        }
+        grid[top][left] = '┌';
+        grid[top][right] = '┐';
+        grid[bottom][left] = '└';
+        grid[bottom][right] = '┘';
+
+        // Labels are centered, and cut off if box is too small for them
+        let inner_width = right - left - 1;
+        let labels = placement.labels();
+        let inner_height = bottom - top - 1;
+        let first_row = top + 1 + inner_height.saturating_sub(labels.len()) / 2;
+        for (row, label) in (first_row..bottom).zip(labels) {
+            let label: Vec<char> = label.chars().take(inner_width).collect();
+            let start = left + 1 + (inner_width - label.len()) / 2;
+            grid[row][start..start + label.len()].copy_from_slice(&label);
+        }
+    }
+
+    Ok(grid.into_iter()
+        .map(|row| row.into_iter().collect::<String>().trim_end().to_string() + "\n")
+        .collect())
+}
+
+/// Which dimensions are used for displays in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutView {
+    /// Arrangement as seen by mutter, in layout coordinates
+    Logical,
+    /// Physical sizes reported by displays, placed next to each other in the same order
+    Physical,
+}
+
+/// Space between displays in physical view, in millimeters.
+const PHYSICAL_GAP_MM: i32 = 20;
+
+/// Uses physical size of every enabled display, keeping their left to right order and
+/// aligning them by bottom edge. Mirrored displays are separate devices, so each of them gets
+/// its own entry.
+pub fn physical_placements(current_state: &get_current_state::Response) -> anyhow::Result<Vec<Placement>> {
+    let mut placements: Vec<_> = placements(current_state)?.into_iter()
+        .flat_map(|placement| placement.connectors.clone().into_iter().map(move |connector| Placement { connectors: vec![connector], ..placement.clone() }))
+        .collect();
+    placements.sort_by_key(|placement| (placement.x, placement.y));
+    for placement in &mut placements {
+        let connector = placement.name();
+        let monitor = current_state.monitors.iter()
+            .find(|monitor| monitor.id.connector == connector)
+            .ok_or(anyhow!("could not find a display with \"{}\" connector name", connector))?;
+        let (width_mm, height_mm) = monitor.properties.width_mm.zip(monitor.properties.height_mm)
+            .filter(|(width_mm, height_mm)| *width_mm > 0 && *height_mm > 0)
+            .ok_or(anyhow!("display \"{}\" does not report its physical size", connector))?;
+        (placement.width, placement.height) = if placement.transform as u32 % 2 == 1 {
+            (height_mm, width_mm)
+        } else {
+            (width_mm, height_mm)
+        };
+    }
+    let max_height = placements.iter().map(|placement| placement.height).max().unwrap_or_default();
+    let mut x = 0;
+    for placement in &mut placements {
+        placement.x = x;
+        placement.y = max_height - placement.height;
+        x += placement.width + PHYSICAL_GAP_MM;
+    }
+    Ok(placements)
+}
+
+/// Draws displays as an SVG image, with the same labels as [`render_text`]. Physical view
+/// additionally shows diagonal of every display.
+pub fn render_svg(current_state: &get_current_state::Response, view: LayoutView) -> anyhow::Result<String> {
+    let placements = match view {
+        LayoutView::Logical => placements(current_state)?,
+        LayoutView::Physical => physical_placements(current_state)?,
+    };
+    let total_width = placements.iter().map(|p| p.x + p.width).max().unwrap_or_default().max(1);
+    let total_height = placements.iter().map(|p| p.y + p.height).max().unwrap_or_default().max(1);
+    let margin = total_width.max(total_height) / 50;
+    let font_size = placements.iter().map(|p| p.height.min(p.width)).min().unwrap_or_default() / 10;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" font-family=\"sans-serif\" font-size=\"{font_size}\">\n",
+        -margin, -margin, total_width + 2 * margin, total_height + 2 * margin,
+    );
+    for placement in &placements {
+        let fill = if placement.primary { "#d0e4f5" } else { "#eeeeee" };
+        svg += &format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{fill}\" stroke=\"#333333\" stroke-width=\"{}\"/>\n",
+            placement.x, placement.y, placement.width, placement.height, (font_size / 8).max(1),
+        );
+        let mut labels = placement.labels();
+        if view == LayoutView::Physical {
+            let diagonal_in = (placement.width as f64).hypot(placement.height as f64) / 25.4;
+            labels.push(format!("{}x{}mm, {diagonal_in:.1}\"", placement.width, placement.height));
+        }
+        let center_x = placement.x + placement.width / 2;
+        let first_line = placement.y + placement.height / 2 - font_size * (labels.len() as i32 - 1) * 6 / 10;
+        for (line, label) in labels.iter().enumerate() {
+            svg += &format!(
+                "  <text x=\"{center_x}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"middle\">{}</text>\n",
+                first_line + font_size * line as i32 * 12 / 10,
+                quick_xml::escape::escape(label.as_str()),
+            );
+        }
+    }
+    svg += "</svg>\n";
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    /// Laptop mirrored to a projector, with another display on the right
+    fn presentation() -> get_current_state::Response {
+        state(
+            vec![
+                monitor("eDP-1", &[(1920, 1080)]),
+                monitor("HDMI-1", &[(1920, 1080)]),
+                monitor("DP-1", &[(2560, 1440)]),
+            ],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1", "HDMI-1"]), logical_monitor(1920, 0, 2.0, &["DP-1"])],
+        )
+    }
+
+    #[test]
+    fn mirrored_displays_are_drawn_as_one_box() {
+        let text = render_text(&presentation(), 60).unwrap();
+        assert_eq!(text.matches('┌').count(), 2);
+        assert_eq!(text.matches("HDMI-1").count(), 1);
+        assert!(text.contains("eDP-1+HDMI-1 (primary)"));
+        assert!(text.contains("scale 2.00"));
+    }
+
+    #[test]
+    fn mirrored_displays_are_separate_only_in_physical_svg() {
+        let svg = render_svg(&presentation(), LayoutView::Logical).unwrap();
+        assert_eq!(svg.matches("<rect").count(), 2);
+        assert!(svg.contains(">eDP-1+HDMI-1 (primary)</text>"));
+
+        let mut current_state = presentation();
+        assert!(render_svg(&current_state, LayoutView::Physical).is_err());
+        for (monitor, (width_mm, height_mm)) in current_state.monitors.iter_mut().zip([(344, 194), (1771, 996), (597, 336)]) {
+            monitor.properties.width_mm = Some(width_mm);
+            monitor.properties.height_mm = Some(height_mm);
+        }
+        let svg = render_svg(&current_state, LayoutView::Physical).unwrap();
+        assert_eq!(svg.matches("<rect").count(), 3);
+        assert!(svg.contains(">eDP-1 (primary)</text>"));
+        assert!(svg.contains(">HDMI-1 (primary)</text>"));
+        assert!(svg.contains(">1771x996mm, 80.0&quot;</text>"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::display_config::get_current_state::{self, MonitorColorMode, RefreshRateMode};
+
+/// Single difference between two consecutive display configuration states
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum Change {
+    Connected { connector: String },
+    Disconnected { connector: String },
+    /// absence of a mode means that monitor is disabled
+    Mode { connector: String, from: Option<String>, to: Option<String> },
+    Scale { connector: String, from: Option<f64>, to: Option<f64> },
+    Position { connector: String, from: Option<(i32, i32)>, to: Option<(i32, i32)> },
+    Primary { connector: String, from: bool, to: bool },
+    Hdr { connector: String, from: bool, to: bool },
+    Vrr { connector: String, from: bool, to: bool },
+    /// absence of a value means that privacy screen is not supported
+    PrivacyScreen { connector: String, from: Option<bool>, to: Option<bool> },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn opt<T: fmt::Display>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or("none".into())
+        }
+        fn on_off(flag: &bool) -> &'static str {
+            if *flag { "on" } else { "off" }
+        }
+        match self {
+            Change::Connected { connector } => write!(f, "{connector}: connected"),
+            Change::Disconnected { connector } => write!(f, "{connector}: disconnected"),
+            Change::Mode { connector, from, to } => write!(f, "{connector}: mode {} -> {}", opt(from), opt(to)),
+            Change::Scale { connector, from, to } => write!(f, "{connector}: scale {} -> {}", opt(from), opt(to)),
+            Change::Position { connector, from, to } => {
+                let position = |position: &Option<(i32, i32)>| position.map(|(x, y)| format!("{x},{y}")).unwrap_or("none".into());
+                write!(f, "{connector}: position {} -> {}", position(from), position(to))
+            },
+            Change::Primary { connector, from, to } => write!(f, "{connector}: primary {} -> {}", on_off(from), on_off(to)),
+            Change::Hdr { connector, from, to } => write!(f, "{connector}: HDR {} -> {}", on_off(from), on_off(to)),
+            Change::Vrr { connector, from, to } => write!(f, "{connector}: VRR {} -> {}", on_off(from), on_off(to)),
+            Change::PrivacyScreen { connector, from, to } => {
+                let state = |state: &Option<bool>| state.as_ref().map(on_off).unwrap_or("unsupported");
+                write!(f, "{connector}: privacy screen {} -> {}", state(from), state(to))
+            },
+        }
+    }
+}
+
+/// Parts of monitor state that are tracked for changes
+#[derive(PartialEq)]
+struct MonitorSnapshot {
+    connector: String,
+    mode: Option<String>,
+    scale: Option<f64>,
+    position: Option<(i32, i32)>,
+    primary: bool,
+    hdr: bool,
+    vrr: bool,
+    privacy_screen: Option<bool>,
+}
+
+impl MonitorSnapshot {
+    fn new(state: &get_current_state::Response, monitor: &get_current_state::Monitor) -> Self {
+        let logical_monitor = state.logical_monitors.iter().find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id));
+        let current_mode = logical_monitor.and_then(|_| monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false)));
+        Self {
+            connector: monitor.id.connector.clone(),
+            mode: current_mode.map(|mode| mode.id.clone()),
+            scale: logical_monitor.map(|logical_monitor| logical_monitor.scale),
+            position: logical_monitor.map(|logical_monitor| (logical_monitor.x, logical_monitor.y)),
+            primary: logical_monitor.is_some_and(|logical_monitor| logical_monitor.primary),
+            hdr: monitor.properties.color_mode.is_some_and(|mode| mode == MonitorColorMode::BT2100),
+            vrr: current_mode.is_some_and(|mode| mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable)),
+            privacy_screen: monitor.properties.privacy_screen_state.map(|(enabled, _)| enabled),
+        }
+    }
+}
+
+/// Computes list of changes between `old` and `new` states, in order of monitors in `new` state
+/// followed by monitors that were disconnected.
+pub fn diff_states(old: &get_current_state::Response, new: &get_current_state::Response) -> Vec<Change> {
+    let old_snapshots: Vec<_> = old.monitors.iter().map(|monitor| MonitorSnapshot::new(old, monitor)).collect();
+    let new_snapshots: Vec<_> = new.monitors.iter().map(|monitor| MonitorSnapshot::new(new, monitor)).collect();
+
+    let mut changes = Vec::new();
+    for new_snapshot in &new_snapshots {
+        let Some(old_snapshot) = old_snapshots.iter().find(|old_snapshot| old_snapshot.connector == new_snapshot.connector) else {
+            changes.push(Change::Connected { connector: new_snapshot.connector.clone() });
+            continue;
+        };
+        let connector = new_snapshot.connector.clone();
+        if old_snapshot.mode != new_snapshot.mode {
+            changes.push(Change::Mode { connector: connector.clone(), from: old_snapshot.mode.clone(), to: new_snapshot.mode.clone() });
+        }
+        if old_snapshot.scale != new_snapshot.scale {
+            changes.push(Change::Scale { connector: connector.clone(), from: old_snapshot.scale, to: new_snapshot.scale });
+        }
+        if old_snapshot.position != new_snapshot.position {
+            changes.push(Change::Position { connector: connector.clone(), from: old_snapshot.position, to: new_snapshot.position });
+        }
+        if old_snapshot.primary != new_snapshot.primary {
+            changes.push(Change::Primary { connector: connector.clone(), from: old_snapshot.primary, to: new_snapshot.primary });
+        }
+        if old_snapshot.hdr != new_snapshot.hdr {
+            changes.push(Change::Hdr { connector: connector.clone(), from: old_snapshot.hdr, to: new_snapshot.hdr });
+        }
+        if old_snapshot.vrr != new_snapshot.vrr {
+            changes.push(Change::Vrr { connector: connector.clone(), from: old_snapshot.vrr, to: new_snapshot.vrr });
+        }
+        if old_snapshot.privacy_screen != new_snapshot.privacy_screen {
+            changes.push(Change::PrivacyScreen { connector, from: old_snapshot.privacy_screen, to: new_snapshot.privacy_screen });
+        }
+    }
+    for old_snapshot in &old_snapshots {
+        if !new_snapshots.iter().any(|new_snapshot| new_snapshot.connector == old_snapshot.connector) {
+            changes.push(Change::Disconnected { connector: old_snapshot.connector.clone() });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    fn describe(changes: &[Change]) -> Vec<String> {
+        changes.iter().map(Change::to_string).collect()
+    }
+
+    #[test]
+    fn same_state_has_no_changes() {
+        let current_state = state(vec![monitor("eDP-1", &[(1920, 1200)])], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        assert_eq!(diff_states(&current_state, &current_state), []);
+    }
+
+    #[test]
+    fn plugging_display_in_and_enabling_it() {
+        let old = state(vec![monitor("eDP-1", &[(1920, 1200)])], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let new = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.0, &["DP-1"])],
+        );
+        assert_eq!(describe(&diff_states(&old, &new)), ["DP-1: connected"]);
+        assert_eq!(describe(&diff_states(&new, &old)), ["DP-1: disconnected"]);
+    }
+
+    #[test]
+    fn disabling_display_and_moving_primary() {
+        let old = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.5, &["DP-1"])],
+        );
+        let new = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.5, &["DP-1"])],
+        );
+        assert_eq!(describe(&diff_states(&old, &new)), [
+            "eDP-1: mode 1920x1200@60.000 -> none",
+            "eDP-1: scale 1 -> none",
+            "eDP-1: position 0,0 -> none",
+            "eDP-1: primary on -> off",
+            "DP-1: position 1920,0 -> 0,0",
+            "DP-1: primary off -> on",
+        ]);
+    }
+
+    #[test]
+    fn mirrored_display_takes_position_of_the_other_one() {
+        let old = state(
+            vec![monitor("eDP-1", &[(1920, 1080)]), monitor("HDMI-1", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.0, &["HDMI-1"])],
+        );
+        let new = state(
+            vec![monitor("eDP-1", &[(1920, 1080)]), monitor("HDMI-1", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1", "HDMI-1"])],
+        );
+        // Primary flag belongs to logical monitor, so it's shared by mirrored displays
+        assert_eq!(describe(&diff_states(&old, &new)), ["HDMI-1: position 1920,0 -> 0,0", "HDMI-1: primary off -> on"]);
+    }
+
+    #[test]
+    fn hdr_and_privacy_screen_changes() {
+        let old = state(vec![monitor("eDP-1", &[(1920, 1200)])], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let mut new = old.clone();
+        new.monitors[0].properties.color_mode = Some(MonitorColorMode::BT2100);
+        new.monitors[0].properties.privacy_screen_state = Some((true, false));
+        assert_eq!(diff_states(&old, &new), [
+            Change::Hdr { connector: "eDP-1".to_string(), from: false, to: true },
+            Change::PrivacyScreen { connector: "eDP-1".to_string(), from: None, to: Some(true) },
+        ]);
+        assert_eq!(describe(&diff_states(&old, &new))[1], "eDP-1: privacy screen unsupported -> on");
+    }
+}
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+/// Resolves XDG base directory from `var`, falling back to `fallback` relative to home directory
+fn xdg_dir(var: &str, fallback: &str) -> anyhow::Result<PathBuf> {
+    std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)))
+        .ok_or(anyhow!("could not determine directory, neither {var} nor HOME are set"))
+}
+
+/// User config directory, e.g. ~/.config
+pub fn config_dir() -> anyhow::Result<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// User state directory, e.g. ~/.local/state
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
@@ -267,7 +267,7 @@ pub mod apply_monitors_config {
         Persistent = 2,
     }
 
-    #[derive(Debug, Type, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Type, Serialize, Deserialize)]
     pub struct LogicalMonitor {
         /// layout x position
         pub x: i32,
@@ -281,7 +281,7 @@ pub mod apply_monitors_config {
         pub monitors: Vec<Monitor>,
     }
 
-    #[derive(Debug, Type, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Type, Serialize, Deserialize)]
     pub struct Monitor {
         /// connector name
         pub connector: String,
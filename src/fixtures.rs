@@ -0,0 +1,124 @@
+//! Display states for tests, so that planning could be checked without a running mutter.
+
+use crate::display_config::get_current_state::{
+    self, LayoutMode, LogicalMonitorTransform, ModeProperties, MonitorId, MonitorProperties, Properties,
+    RefreshRateMode,
+};
+
+/// Scales that every mode supports, all of them result in integer logical sizes for common
+/// resolutions except for 1.75.
+pub const SCALES: [f64; 5] = [1.0, 1.25, 1.5, 1.75, 2.0];
+
+/// Display with 60Hz modes of given resolutions. The first one is preferred and current.
+pub fn monitor(connector: &str, resolutions: &[(i32, i32)]) -> get_current_state::Monitor {
+    let modes = resolutions.iter().map(|(width, height)| mode(*width, *height, 60.0)).collect();
+    monitor_with_modes(connector, modes)
+}
+
+/// Fixed refresh rate mode, with ID formatted the same way as mutter does it.
+pub fn mode(width: i32, height: i32, refresh_rate: f64) -> get_current_state::Mode {
+    get_current_state::Mode {
+        id: format!("{width}x{height}@{refresh_rate:.3}"),
+        width,
+        height,
+        refresh_rate,
+        preferred_scale: 1.0,
+        supported_scales: SCALES.to_vec(),
+        properties: ModeProperties {
+            is_current: None,
+            is_preferred: None,
+            is_interlaced: None,
+            refresh_rate_mode: None,
+        },
+    }
+}
+
+/// Variable refresh rate counterpart of [`mode`].
+pub fn vrr_mode(width: i32, height: i32, refresh_rate: f64) -> get_current_state::Mode {
+    let mut mode = mode(width, height, refresh_rate);
+    mode.id.push_str("+vrr");
+    mode.properties.refresh_rate_mode = Some(RefreshRateMode::Variable);
+    mode
+}
+
+/// Display with given modes. The first one is preferred and current.
+pub fn monitor_with_modes(connector: &str, mut modes: Vec<get_current_state::Mode>) -> get_current_state::Monitor {
+    for (index, mode) in modes.iter_mut().enumerate() {
+        mode.properties.is_current = Some(index == 0);
+        mode.properties.is_preferred = Some(index == 0);
+    }
+    get_current_state::Monitor {
+        id: MonitorId {
+            connector: connector.to_string(),
+            vendor: "GSM".to_string(),
+            product: format!("Display {connector}"),
+            serial: format!("{connector}-serial"),
+        },
+        modes,
+        properties: MonitorProperties {
+            width_mm: None,
+            height_mm: None,
+            is_underscanning: None,
+            max_screen_size: None,
+            is_builtin: Some(connector.starts_with("eDP")),
+            display_name: None,
+            privacy_screen_state: None,
+            min_refresh_rate: None,
+            is_for_lease: None,
+            color_mode: None,
+            supported_color_modes: None,
+        },
+    }
+}
+
+/// Logical monitor showing displays with given connectors, which are filled in by [`state`].
+pub fn logical_monitor(x: i32, y: i32, scale: f64, connectors: &[&str]) -> get_current_state::LogicalMonitor {
+    get_current_state::LogicalMonitor {
+        x,
+        y,
+        scale,
+        transform: LogicalMonitorTransform::Normal,
+        primary: false,
+        monitors: connectors.iter().map(|connector| MonitorId {
+            connector: connector.to_string(),
+            vendor: String::new(),
+            product: String::new(),
+            serial: String::new(),
+        }).collect(),
+        properties: get_current_state::LogicalMonitorProperties {},
+    }
+}
+
+/// State in logical layout mode. Displays that are not in any of `logical_monitors` are
+/// disabled, and the first logical monitor is primary unless another one is.
+pub fn state(mut monitors: Vec<get_current_state::Monitor>, mut logical_monitors: Vec<get_current_state::LogicalMonitor>) -> get_current_state::Response {
+    for logical_monitor in &mut logical_monitors {
+        for monitor_id in &mut logical_monitor.monitors {
+            if let Some(monitor) = monitors.iter().find(|monitor| monitor.id == *monitor_id) {
+                *monitor_id = monitor.id.clone();
+            }
+        }
+    }
+    for monitor in &mut monitors {
+        if !logical_monitors.iter().any(|logical_monitor| logical_monitor.monitors.contains(&monitor.id)) {
+            for mode in &mut monitor.modes {
+                mode.properties.is_current = Some(false);
+            }
+        }
+    }
+    if !logical_monitors.iter().any(|logical_monitor| logical_monitor.primary) {
+        if let Some(first) = logical_monitors.first_mut() {
+            first.primary = true;
+        }
+    }
+    get_current_state::Response {
+        serial: 1,
+        monitors,
+        logical_monitors,
+        properties: Properties {
+            layout_mode: Some(LayoutMode::Logical),
+            supports_changing_layout_mode: Some(true),
+            global_scale_required: Some(false),
+        },
+    }
+}
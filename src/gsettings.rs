@@ -0,0 +1,26 @@
+use anyhow::{anyhow, Context};
+
+async fn gsettings(args: &[&str]) -> anyhow::Result<String> {
+    let output = tokio::process::Command::new("gsettings")
+        .args(args)
+        .output()
+        .await
+        .context("could not run gsettings")?;
+    if !output.status.success() {
+        return Err(anyhow!("gsettings {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads a GSettings key, e.g. one of GNOME settings daemon plugins.
+pub async fn get<T: std::str::FromStr>(schema: &str, key: &str) -> anyhow::Result<T> {
+    let value = gsettings(&["get", schema, key]).await?;
+    // Values of types that are not inferred from text are prefixed with type, e.g. "uint32 2700"
+    let value = value.rsplit(' ').next().unwrap_or_default();
+    value.parse().map_err(|_| anyhow!("unexpected value of {key}: \"{value}\""))
+}
+
+/// Writes a GSettings key, `value` is in GVariant text format.
+pub async fn set(schema: &str, key: &str, value: &str) -> anyhow::Result<()> {
+    gsettings(&["set", schema, key, value]).await.map(|_| ())
+}
@@ -0,0 +1,211 @@
+use std::{cmp::Reverse, path::PathBuf};
+
+use anyhow::anyhow;
+use chrono::{Local, NaiveDateTime};
+
+use crate::{
+    arrange::fix_layout,
+    dirs,
+    display_config::{apply_monitors_config, get_current_state::{self, MonitorColorMode}},
+    planner::match_displays,
+    state_file,
+};
+
+/// How many previous configurations are kept, older ones are removed when new ones are recorded.
+const HISTORY_SIZE: usize = 20;
+
+/// File names are timestamps, so that sorting them by name sorts them by time as well.
+const FILE_NAME_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+const FILE_EXTENSION: &str = "state";
+
+/// Configuration that was active before some change was applied.
+pub struct Entry {
+    pub path: PathBuf,
+    pub time: NaiveDateTime,
+    pub state: get_current_state::Response,
+}
+
+impl Entry {
+    /// Short summary of enabled displays, e.g. "eDP-1 2560x1600@60.000 HDR at 167% (primary)".
+    pub fn describe(&self) -> String {
+        let displays: Vec<_> = self.state.logical_monitors.iter().flat_map(|logical_monitor| {
+            logical_monitor.monitors.iter().map(move |monitor_id| {
+                let monitor = self.state.monitors.iter().find(|monitor| &monitor.id == monitor_id);
+                let mode = monitor
+                    .and_then(|monitor| monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false)))
+                    .map(|mode| format!("{}x{}@{:.3}", mode.width, mode.height, mode.refresh_rate))
+                    .unwrap_or_default();
+                let hdr = monitor.is_some_and(|monitor| monitor.properties.color_mode == Some(MonitorColorMode::BT2100));
+                let hdr = if hdr { " HDR" } else { "" };
+                let primary = if logical_monitor.primary { " (primary)" } else { "" };
+                format!("{} {mode}{hdr} at {:.0}%{primary}", monitor_id.connector, logical_monitor.scale * 100.0)
+            })
+        }).collect();
+        displays.join(", ")
+    }
+}
+
+fn history_dir() -> anyhow::Result<PathBuf> {
+    Ok(dirs::state_dir()?.join("displayconfig-mutter").join("history"))
+}
+
+/// Saves configuration that is about to be replaced, removing the oldest entries above
+/// history size.
+pub fn record(current_state: &get_current_state::Response) -> anyhow::Result<()> {
+    let dir = history_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let name = format!("{}.{FILE_EXTENSION}", Local::now().naive_local().format(FILE_NAME_FORMAT));
+    state_file::write(&dir.join(name), current_state)?;
+    for entry in entries()?.into_iter().skip(HISTORY_SIZE) {
+        std::fs::remove_file(entry.path)?;
+    }
+    Ok(())
+}
+
+/// Removes entries from history, e.g. once they are restored by `undo`, so that the next
+/// `undo` goes further back instead of returning to configuration that was undone.
+pub fn forget(entries: &[Entry]) -> anyhow::Result<()> {
+    for entry in entries {
+        std::fs::remove_file(&entry.path)?;
+    }
+    Ok(())
+}
+
+/// Lists recorded configurations, most recent first. Entries that could not be read are
+/// skipped.
+pub fn entries() -> anyhow::Result<Vec<Entry>> {
+    let dir = history_dir()?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|dir_entry| {
+            let path = dir_entry.ok()?.path();
+            if path.extension()? != FILE_EXTENSION {
+                return None;
+            }
+            let time = NaiveDateTime::parse_from_str(path.file_stem()?.to_str()?, FILE_NAME_FORMAT).ok()?;
+            let state = state_file::read(&path).ok()?;
+            Some(Entry { path, time, state })
+        })
+        .collect();
+    entries.sort_by_key(|entry| Reverse(entry.time));
+    Ok(entries)
+}
+
+/// Plans a config that restores layout from `saved` state. Displays that are not connected
+/// anymore are left out, and layout is repacked if that leaves gaps.
+pub fn plan_restore(current_state: &get_current_state::Response, saved: &get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    let saved_ids: Vec<_> = saved.logical_monitors.iter().flat_map(|logical_monitor| logical_monitor.monitors.iter().cloned()).collect();
+    let matched = match_displays(current_state, &saved_ids);
+    let mut logical_monitors = Vec::new();
+    let mut left_out = false;
+    for saved_logical_monitor in &saved.logical_monitors {
+        let mut monitors = Vec::new();
+        for saved_id in &saved_logical_monitor.monitors {
+            let Some(monitor) = matched.get(&saved_id.connector) else {
+                left_out = true;
+                continue;
+            };
+            let saved_monitor = saved.monitors.iter()
+                .find(|saved_monitor| &saved_monitor.id == saved_id)
+                .ok_or(anyhow!("saved configuration does not have details of \"{}\"", saved_id.connector))?;
+            let saved_mode = saved_monitor.modes.iter()
+                .find(|mode| mode.properties.is_current.unwrap_or(false))
+                .ok_or(anyhow!("saved configuration does not have current mode of \"{}\"", saved_id.connector))?;
+            let mode = monitor.modes.iter()
+                .find(|mode| *mode == saved_mode)
+                .ok_or(anyhow!("display \"{}\" does not have {} mode anymore", monitor.id.connector, saved_mode.id))?;
+            monitors.push(apply_monitors_config::Monitor {
+                connector: monitor.id.connector.clone(),
+                mode: mode.id.clone(),
+                properties: apply_monitors_config::MonitorProperties {
+                    underscanning: saved_monitor.properties.is_underscanning,
+                    color_mode: monitor.properties.supported_color_modes.as_ref().and(saved_monitor.properties.color_mode),
+                },
+            });
+        }
+        if !monitors.is_empty() {
+            logical_monitors.push(apply_monitors_config::LogicalMonitor {
+                x: saved_logical_monitor.x,
+                y: saved_logical_monitor.y,
+                scale: saved_logical_monitor.scale,
+                transform: saved_logical_monitor.transform,
+                primary: saved_logical_monitor.primary,
+                monitors,
+            });
+        }
+    }
+    if logical_monitors.is_empty() {
+        return Err(anyhow!("none of the displays from saved configuration are connected"));
+    }
+    if left_out {
+        fix_layout(current_state, saved.properties.layout_mode.unwrap_or_default(), &mut logical_monitors)?;
+    }
+    if !logical_monitors.iter().any(|logical_monitor| logical_monitor.primary) {
+        logical_monitors[0].primary = true;
+    }
+    Ok(logical_monitors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    /// Two displays of the same model without serials, as commonly reported through docks
+    fn identical(connector: &str) -> get_current_state::Monitor {
+        let mut monitor = monitor(connector, &[(2560, 1440)]);
+        monitor.id.product = "U2720Q".to_string();
+        monitor.id.serial = String::new();
+        monitor
+    }
+
+    fn connectors(logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Vec<(&str, i32)> {
+        logical_monitors.iter()
+            .flat_map(|logical_monitor| logical_monitor.monitors.iter().map(|monitor| (monitor.connector.as_str(), logical_monitor.x)))
+            .collect()
+    }
+
+    #[test]
+    fn identical_displays_are_restored_on_their_own_connectors() {
+        let saved = state(
+            vec![identical("DP-1"), identical("DP-2")],
+            vec![logical_monitor(0, 0, 1.0, &["DP-2"]), logical_monitor(2560, 0, 1.0, &["DP-1"])],
+        );
+        let current_state = state(
+            vec![identical("DP-1"), identical("DP-2")],
+            vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(2560, 0, 1.0, &["DP-2"])],
+        );
+        let logical_monitors = plan_restore(&current_state, &saved).unwrap();
+        assert_eq!(connectors(&logical_monitors), [("DP-2", 0), ("DP-1", 2560)]);
+    }
+
+    #[test]
+    fn identical_displays_on_other_ports_are_matched_once() {
+        let saved = state(
+            vec![identical("DP-1"), identical("DP-2")],
+            vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(2560, 0, 1.0, &["DP-2"])],
+        );
+        let current_state = state(
+            vec![identical("DP-3"), identical("DP-4")],
+            vec![logical_monitor(0, 0, 1.0, &["DP-3"]), logical_monitor(2560, 0, 1.0, &["DP-4"])],
+        );
+        let logical_monitors = plan_restore(&current_state, &saved).unwrap();
+        assert_eq!(connectors(&logical_monitors), [("DP-3", 0), ("DP-4", 2560)]);
+    }
+
+    #[test]
+    fn display_that_was_replaced_is_left_out() {
+        let saved = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(2560, 0, 1.0, &["eDP-1"])],
+        );
+        let mut other = monitor("DP-1", &[(2560, 1440)]);
+        other.id.serial = "other".to_string();
+        let current_state = state(vec![monitor("eDP-1", &[(1920, 1200)]), other], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let logical_monitors = plan_restore(&current_state, &saved).unwrap();
+        assert_eq!(connectors(&logical_monitors), [("eDP-1", 0)]);
+        assert!(logical_monitors[0].primary);
+    }
+}
@@ -1,2 +1,23 @@
 pub mod display_config;
+pub mod arrange;
+pub mod builtin;
 pub mod cli;
+#[cfg(feature = "dynamic-completion")]
+pub mod completion;
+pub mod diagram;
+pub mod diff;
+pub mod dirs;
+pub mod gsettings;
+pub mod history;
+pub mod monitors_xml;
+pub mod night_light;
+pub mod planner;
+pub mod profiles;
+pub mod service;
+pub mod state_file;
+pub mod tui;
+pub mod validate;
+pub mod xrandr;
+
+#[cfg(test)]
+mod fixtures;
@@ -1,159 +1,366 @@
-use std::{cmp::Ordering, collections::HashSet};
+use std::{path::Path, time::Duration};
 
-use anyhow::anyhow;
-use clap::Parser;
-use displayconfig_mutter::{cli::{self, Cli}, display_config::{apply_monitors_config, get_current_state::{self, MonitorColorMode, RefreshRateMode}, DisplayConfigProxy}};
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use clap::{CommandFactory, Parser};
+#[cfg(feature = "dynamic-completion")]
+use clap_complete::{env::{self, EnvCompleter}, CompleteEnv};
+use displayconfig_mutter::{arrange::plan_arrange, builtin::{builtin_enabled, builtin_wanted, login1_proxy, plan_builtin, rotation_locked, set_rotation_locked, with_rotation_check}, cli::{self, Cli}, diagram::{render_svg, render_text, LayoutView}, diff::diff_states, history::{self, plan_restore}, display_config::{apply_monitors_config, get_current_state::{self, MonitorColorMode, RefreshRateMode}, DisplayConfigProxy}, monitors_xml::{self, MonitorsXml, MONITORS_XML_VERSION}, night_light::{self, GnomeBackend}, planner::{apply_with_retry, apply_without_history, current_layout_mode, ensure_apply_allowed, physical_dpi, plan_set, wait_for_config}, profiles, service::serve, state_file, tui, validate::layout_problems, xrandr::{format_query, plan_xrandr, XrandrRequest}};
+use futures_util::StreamExt;
 use tabled::{builder::Builder, settings::{object::Rows, Alignment, Modify, Style}};
 
+/// Width of layout diagram when output is not a terminal
+const DEFAULT_COLUMNS: usize = 80;
+
+fn main() -> anyhow::Result<()> {
+    // Completers query mutter on their own, so this has to run outside of async runtime
+    #[cfg(feature = "dynamic-completion")]
+    CompleteEnv::with_factory(Cli::command).complete();
+    run()
+}
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+async fn run() -> anyhow::Result<()> {
+    let mut args: Vec<_> = std::env::args_os().collect();
+    // Behave like xrandr when invoked through a symlink named after it
+    if args.first().and_then(|arg0| Path::new(arg0).file_name()).is_some_and(|name| name == "xrandr") {
+        args.insert(1, "xrandr".into());
+    }
+    let cli = Cli::parse_from(args);
+    if let cli::Command::Completions(args) = &cli.command {
+        return completions(args.shell);
+    }
+    // Saved state can be rendered without a running session
+    if let cli::Command::ExportLayout(args @ cli::ExportLayoutArgs { state: Some(path), .. }) = &cli.command {
+        return export_layout(state_file::read(path)?, args);
+    }
+    // History is stored locally, so it could be listed without a running session
+    if let cli::Command::History = &cli.command {
+        return list_history();
+    }
+
     let conn = zbus::Connection::session().await?;
     let proxy = DisplayConfigProxy::new(&conn).await?;
     let current_state = proxy.get_current_state().await?;
 
     match cli.command {
-        cli::Command::List(cli::ListArgs{connector}) => {
+        cli::Command::List(cli::ListArgs{connector, layout}) => {
             match connector {
                 Some(connector) => list_modes(current_state, connector)?,
+                None if layout => list_layout(current_state)?,
                 None => list_monitors(current_state)?
             };
         },
+        cli::Command::Status => status(&proxy).await?,
+        cli::Command::Watch(args) => watch(&proxy, current_state, args).await?,
+        cli::Command::Xrandr(args) => {
+            let request = XrandrRequest::parse(&args.args)?;
+            if !request.outputs.is_empty() {
+                ensure_apply_allowed(&proxy).await?;
+                let orientation_managed = !args.force && proxy.panel_orientation_managed().await?;
+                let plan = with_rotation_check(orientation_managed, |current_state| plan_xrandr(current_state, &request));
+                apply_with_retry(&proxy, current_state.clone(), apply_monitors_config::Method::Temporary, None, plan).await?;
+            }
+            if request.query {
+                let current_state = proxy.get_current_state().await?;
+                print!("{}", format_query(&current_state)?);
+            }
+        },
+        cli::Command::MonitorsXml(command) => monitors_xml(&proxy, current_state, command).await?,
+        cli::Command::Arrange(args) => {
+            ensure_apply_allowed(&proxy).await?;
+            apply_with_retry(&proxy, current_state, apply_monitors_config::Method::Temporary, None, |current_state| plan_arrange(current_state, args.arrangement)).await?;
+        },
+        cli::Command::Undo(args) => {
+            let entries = history::entries()?;
+            let entry = entries.get(args.steps as usize - 1)
+                .ok_or(anyhow!("only {} previous configurations are recorded", entries.len()))?;
+            ensure_apply_allowed(&proxy).await?;
+            let orientation_managed = !args.force && proxy.panel_orientation_managed().await?;
+            let plan = with_rotation_check(orientation_managed, |current_state| plan_restore(current_state, &entry.state));
+            // Undo is not recorded itself, otherwise undoing twice would go back and forth
+            apply_without_history(&proxy, current_state, apply_monitors_config::Method::Temporary, entry.state.properties.layout_mode, plan).await?;
+            history::forget(&entries[..args.steps as usize])?;
+        },
+        cli::Command::History => unreachable!("history is listed before connecting to mutter"),
+        cli::Command::RotationLock(args) => match args.state {
+            Some(state) => set_rotation_locked(state == cli::Switch::On).await?,
+            None => println!("{}", if rotation_locked().await? { "on" } else { "off" }),
+        },
+        cli::Command::NightLight(command) => {
+            let mut backend = GnomeBackend::new(&conn, &proxy).await;
+            print!("{}", night_light::execute(&mut backend, &command).await?);
+        },
+        cli::Command::Tui => tui::run(&proxy).await?,
+        cli::Command::ExportLayout(args) => export_layout(current_state, &args)?,
+        cli::Command::Builtin(args) => builtin(&proxy, current_state, args).await?,
+        cli::Command::Serve => serve(&conn).await?,
+        cli::Command::Completions(_) => unreachable!("completions are printed before connecting to mutter"),
         cli::Command::Set(args) => {
+            ensure_apply_allowed(&proxy).await?;
+
             let method = if args.persistent {
                 apply_monitors_config::Method::Persistent
             } else {
                 apply_monitors_config::Method::Temporary
             };
 
-            let monitor = current_state.monitors.iter()
-                .find(|monitor| monitor.id.connector == args.connector)
-                .ok_or(anyhow!("could not find a display with \"{}\" connector name", args.connector))?;
-            let logical_monitor = current_state.logical_monitors.iter()
-                .find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id))
-                .ok_or(anyhow!("could not find a logical monitor that is attached to a display with \"{}\" connector name", args.connector))?;
-
-            let mut available_modes = monitor.modes.clone();
-            available_modes.sort();
-            available_modes.reverse();
-            let current_mode = available_modes.iter()
-                .find(|mode| mode.properties.is_current.is_some_and(|f| f))
-                .ok_or(anyhow!("could not find current configuration of \"{}\"", args.connector))?;
-
-            let (width, height) = match (args.max_resolution, args.resolution) {
-                (true, _) => {
-                    available_modes
-                        .first().map(|mode| (mode.width as u32, mode.height as u32))
-                        .ok_or(anyhow!("no modes available for \"{}\"", args.connector))?
-                },
-                (_, Some(res)) => res,
-                _ => (current_mode.width as u32, current_mode.height as u32),
+            // Subscribe before applying, otherwise signal could be missed
+            let monitors_changed = if args.wait {
+                Some(proxy.receive_monitors_changed().await?)
+            } else {
+                None
             };
+            let applied = apply_with_retry(&proxy, current_state, method, None, |current_state| plan_set(current_state, &args)).await?;
+            if let Some(monitors_changed) = monitors_changed {
+                wait_for_config(&proxy, monitors_changed, &applied, Duration::from_secs(args.wait_timeout)).await?;
+            }
+        }
+    }
 
-            let mut available_refresh_rates: Vec<_> = available_modes.iter().filter_map(|mode| {
-                if mode.width as u32 == width && mode.height as u32 == height {
-                    Some(mode.refresh_rate)
-                } else {
-                    None
-                }
-            }).collect();
-            let refresh_rate_cmp = |l: &f64, r: &f64, target: f64| {
-                let l = (l - target).abs() * 100.0;
-                let r = (r - target).abs() * 100.0;
-                (l as u32).cmp(&(r as u32))
-            };
-            let refresh_rate = match (args.max_refresh_rate, args.refresh_rate) {
-                (true, _) => available_refresh_rates.first().ok_or(anyhow!("could not find any refresh rate for {}x{} resolution", width, height))?,
-                (_, Some(refresh_rate)) => {
-                    available_refresh_rates.sort_by(|l, r| refresh_rate_cmp(l, r, refresh_rate));
-                    available_refresh_rates.first().ok_or(anyhow!("could not find refresh rate for {}x{} resolution that is close to {}", width, height, refresh_rate))?
+    Ok(())
+}
+
+async fn builtin(proxy: &DisplayConfigProxy<'_>, mut current_state: get_current_state::Response, args: cli::BuiltinArgs) -> anyhow::Result<()> {
+    let login1 = login1_proxy().await;
+    // logind doesn't signal lid changes, but mutter reconfigures displays when lid is opened or
+    // closed, so MonitorsChanged covers both
+    let mut monitors_changed = proxy.receive_monitors_changed().await?;
+    loop {
+        let lid_closed = match &login1 {
+            Some(login1) => login1.lid_closed().await.ok(),
+            None => None,
+        };
+        let result = apply_builtin_policy(proxy, current_state, args.policy, lid_closed).await;
+        if !args.watch {
+            return result;
+        }
+        // Failures are expected while watching, e.g. when displays are plugged while the screen
+        // is locked, so the policy is just applied again on the next change
+        if let Err(err) = result {
+            eprintln!("error: {err:#}");
+        }
+
+        if monitors_changed.next().await.is_none() {
+            return Err(anyhow!("lost connection to mutter"));
+        }
+        current_state = proxy.get_current_state().await?;
+    }
+}
+
+async fn apply_builtin_policy(
+    proxy: &DisplayConfigProxy<'_>,
+    current_state: get_current_state::Response,
+    policy: cli::BuiltinPolicy,
+    lid_closed: Option<bool>,
+) -> anyhow::Result<()> {
+    let enable = builtin_wanted(&current_state, policy, lid_closed)?;
+    if builtin_enabled(&current_state)? != enable {
+        ensure_apply_allowed(proxy).await?;
+        apply_with_retry(proxy, current_state, apply_monitors_config::Method::Temporary, None, |current_state| plan_builtin(current_state, enable)).await?;
+    }
+    Ok(())
+}
+
+async fn status(proxy: &DisplayConfigProxy<'_>) -> anyhow::Result<()> {
+    let yes_no = |flag: bool| if flag { "Yes" } else { "No" }.to_string();
+    let mut table_builder = Builder::new();
+    table_builder.push_record(["Property", "Value"]);
+    table_builder.push_record(["Applying config allowed".to_string(), yes_no(proxy.apply_monitors_config_allowed().await?)]);
+    table_builder.push_record(["Has external monitor".to_string(), yes_no(proxy.has_external_monitor().await?)]);
+    table_builder.push_record(["Night light supported".to_string(), yes_no(proxy.night_light_supported().await?)]);
+    table_builder.push_record(["Panel orientation managed".to_string(), yes_no(proxy.panel_orientation_managed().await?)]);
+    // Rotation lock is stored by GNOME settings daemon, which might not be installed
+    let rotation_lock = rotation_locked().await.map(yes_no).unwrap_or("Unknown".to_string());
+    table_builder.push_record(["Rotation locked".to_string(), rotation_lock]);
+
+    let mut table = table_builder.build();
+    table
+        .with(Style::modern())
+        .with(Modify::new(Rows::new(1..)).with(Alignment::left()));
+    println!("{table}");
+    Ok(())
+}
+
+async fn monitors_xml(proxy: &DisplayConfigProxy<'_>, current_state: get_current_state::Response, command: cli::MonitorsXmlCommand) -> anyhow::Result<()> {
+    match command {
+        cli::MonitorsXmlCommand::Export(args) => {
+            let configuration = monitors_xml::Configuration::from_state(&current_state)?;
+            let output = match args.profile {
+                Some(name) => {
+                    let path = profiles::path(&name)?;
+                    if let Some(dir) = path.parent() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                    Some(path)
                 },
-                _ => {
-                    available_refresh_rates.sort_by(|l, r| refresh_rate_cmp(l, r, current_mode.refresh_rate));
-                    available_refresh_rates.first().ok_or(anyhow!("could not find refresh rate for {}x{} resolution that is close to current one", width, height))?
-                }
+                None => args.output,
             };
+            let mut xml = match (&output, args.merge) {
+                (Some(path), true) if path.exists() => monitors_xml::read_for_update(path)?,
+                _ => MonitorsXml { version: MONITORS_XML_VERSION, configurations: Vec::new() },
+            };
+            match xml.matching_configuration(&current_state) {
+                Some((index, _)) => xml.configurations[index] = configuration,
+                None => xml.configurations.push(configuration),
+            }
+            match output {
+                Some(path) => std::fs::write(&path, xml.to_xml()?).with_context(|| format!("could not write {}", path.display()))?,
+                None => print!("{}", xml.to_xml()?),
+            }
+        },
+        cli::MonitorsXmlCommand::Import(args) => {
+            let path = match (args.profile, args.file) {
+                (Some(name), _) => profiles::path(&name)?,
+                (None, Some(file)) => file,
+                (None, None) => monitors_xml::default_path()?,
+            };
+            let xml = monitors_xml::read(&path)?;
+            let configuration = match args.configuration {
+                Some(number) => xml.configurations.get(number as usize - 1)
+                    .ok_or(anyhow!("{} has only {} configurations", path.display(), xml.configurations.len()))?,
+                None => xml.matching_configuration(&current_state)
+                    .map(|(_, configuration)| configuration)
+                    .ok_or(anyhow!("{} does not have a configuration for currently connected displays", path.display()))?,
+            };
+            let problems = configuration.problems();
+            if !problems.is_empty() {
+                return Err(anyhow!("configuration is invalid:\n{}", problems.join("\n")));
+            }
 
-            let matching_mode = if args.vrr.is_some_and(|flag| flag) {
-                available_modes.iter()
-                    .find(|mode| 
-                        mode.width as u32 == width && mode.height as u32 == height 
-                        && mode.refresh_rate == *refresh_rate 
-                        && mode.properties.refresh_rate_mode.is_some_and(|mode| mode == RefreshRateMode::Variable))
-                    .ok_or(anyhow!("VRR is not available"))?
+            ensure_apply_allowed(proxy).await?;
+            let method = if args.persistent {
+                apply_monitors_config::Method::Persistent
             } else {
-                available_modes.iter()
-                    .find(|mode| 
-                        mode.width as u32 == width && mode.height as u32 == height 
-                        && mode.refresh_rate == *refresh_rate 
-                        && (mode.properties.refresh_rate_mode.is_none() || mode.properties.refresh_rate_mode.is_some_and(|mode| mode == RefreshRateMode::Fixed)))
-                    .expect("already matched a mode, but couldn't find one without VRR")
+                apply_monitors_config::Method::Temporary
             };
+            let orientation_managed = !args.force && proxy.panel_orientation_managed().await?;
+            let plan = with_rotation_check(orientation_managed, |current_state| configuration.plan(current_state));
+            apply_with_retry(proxy, current_state, method, configuration.layout_mode()?, plan).await?;
+        },
+        cli::MonitorsXmlCommand::Validate(args) => {
+            let path = args.file.map(Ok).unwrap_or_else(monitors_xml::default_path)?;
+            let xml = monitors_xml::read(&path)?;
+            let mut valid = true;
+            for (i, configuration) in xml.configurations.iter().enumerate() {
+                let matches = configuration.matches(&current_state);
+                println!("Configuration {}: {}{}", i + 1, configuration.describe(), if matches { " [connected]" } else { "" });
+                let mut problems = configuration.problems();
+                if matches && problems.is_empty() {
+                    // Only configuration for connected displays could be checked against their modes
+                    match configuration.plan(&current_state) {
+                        Ok(logical_monitors) => {
+                            let layout_mode = configuration.layout_mode().ok().flatten().unwrap_or_else(|| current_layout_mode(&current_state));
+                            problems.extend(layout_problems(&current_state, layout_mode, &logical_monitors));
+                        },
+                        Err(err) => problems.push(err.to_string()),
+                    }
+                }
+                for problem in &problems {
+                    println!("  error: {problem}");
+                }
+                valid &= problems.is_empty();
+            }
+            if !valid {
+                return Err(anyhow!("{} contains invalid configurations", path.display()));
+            }
+        },
+    }
+    Ok(())
+}
 
-            let mut supported_scales = matching_mode.supported_scales.clone();
-            let wanted_scale = args.scaling.map(|scale_precent| scale_precent as f64 / 100.0).unwrap_or(logical_monitor.scale);
-            supported_scales.sort_by(|l, r| {
-                let l = (l * 100.0) as i32;
-                let r = (r * 100.0) as i32;
-                let wanted_scale = (wanted_scale * 100.0) as i32;
-                (l - wanted_scale as i32).abs().cmp(&(r - wanted_scale as i32).abs())
-            });
-            let scale = supported_scales.first().ok_or(anyhow!("display \"{}\" does not have any supported scales", args.connector))?;
-            if (wanted_scale * 4.0).round() != (scale * 4.0).round() {
-                return Err(anyhow!("display \"{}\" does not have any scale close to {}%", args.connector, (wanted_scale * 100.0) as u32));
-            }
-
-            let hdr_supported = monitor.properties.supported_color_modes.as_ref().is_some_and(|modes| modes.contains(&MonitorColorMode::BT2100));
-            let color_mode = args.hdr.map(|hdr| if hdr {MonitorColorMode::BT2100} else {MonitorColorMode::Default})
-                .unwrap_or(monitor.properties.color_mode.unwrap_or(MonitorColorMode::Default));
-            let color_mode = match(color_mode, hdr_supported) {
-                (MonitorColorMode::BT2100, false) => return Err(anyhow!("display \"{}\" does not support HDR", args.connector)),
-                (MonitorColorMode::Default, false) => None,
-                (mode, true) => Some(mode),
-            };
+/// Prints a script that registers dynamic completions, which call back into this binary
+#[cfg(feature = "dynamic-completion")]
+fn completions(shell: cli::Shell) -> anyhow::Result<()> {
+    let completer: &dyn EnvCompleter = match shell {
+        cli::Shell::Bash => &env::Bash,
+        cli::Shell::Zsh => &env::Zsh,
+        cli::Shell::Fish => &env::Fish,
+    };
+    let name = Cli::command().get_name().to_string();
+    let bin = std::env::current_exe()?;
+    completer.write_registration("COMPLETE", &name, &name, &bin.to_string_lossy(), &mut std::io::stdout())?;
+    Ok(())
+}
 
-            proxy.apply_monitors_config(
-                current_state.serial, 
-                method, 
-                vec![
-                    apply_monitors_config::LogicalMonitor{
-                        x: logical_monitor.x,
-                        y: logical_monitor.y,
-                        scale: *scale,
-                        transform: logical_monitor.transform,
-                        primary: logical_monitor.primary,
-                        monitors: vec![apply_monitors_config::Monitor {
-                            connector: monitor.id.connector.clone(),
-                            mode: matching_mode.id.clone(),
-                            properties: apply_monitors_config::MonitorProperties {
-                                underscanning: None,
-                                color_mode: color_mode,
-                            }
-                        }]
-                    }
-                ], 
-                apply_monitors_config::Properties{
-                    layout_mode: None,
-                    monitors_for_lease: None, 
-                },
-            ).await?;
+/// Static script that only completes subcommands and options, without querying mutter
+#[cfg(not(feature = "dynamic-completion"))]
+fn completions(shell: cli::Shell) -> anyhow::Result<()> {
+    let shell = match shell {
+        cli::Shell::Bash => clap_complete::Shell::Bash,
+        cli::Shell::Zsh => clap_complete::Shell::Zsh,
+        cli::Shell::Fish => clap_complete::Shell::Fish,
+    };
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
+
+async fn watch(proxy: &DisplayConfigProxy<'_>, mut current_state: get_current_state::Response, args: cli::WatchArgs) -> anyhow::Result<()> {
+    let mut monitors_changed = proxy.receive_monitors_changed().await?;
+    while monitors_changed.next().await.is_some() {
+        let new_state = proxy.get_current_state().await?;
+        let changes = diff_states(&current_state, &new_state);
+        let timestamp = Local::now();
+        if args.json {
+            let event = serde_json::json!({
+                "timestamp": timestamp.to_rfc3339(),
+                "serial": new_state.serial,
+                "changes": changes,
+            });
+            println!("{event}");
+        } else {
+            for change in &changes {
+                println!("[{}] {change}", timestamp.format("%Y-%m-%d %H:%M:%S%.3f"));
+            }
         }
+        current_state = new_state;
+    }
+    Err(anyhow!("lost connection to mutter"))
+}
+
+fn list_history() -> anyhow::Result<()> {
+    for (number, entry) in history::entries()?.iter().enumerate() {
+        println!("{:>3}  {}  {}", number + 1, entry.time.format("%Y-%m-%d %H:%M:%S"), entry.describe());
+    }
+    Ok(())
+}
+
+fn export_layout(current_state: get_current_state::Response, args: &cli::ExportLayoutArgs) -> anyhow::Result<()> {
+    if let Some(path) = &args.save_state {
+        state_file::write(path, &current_state)?;
+    }
+    if let Some(path) = &args.svg {
+        let view = if args.physical { LayoutView::Physical } else { LayoutView::Logical };
+        std::fs::write(path, render_svg(&current_state, view)?).with_context(|| format!("could not write {}", path.display()))?;
     }
+    Ok(())
+}
 
+fn list_layout(current_state: get_current_state::Response) -> anyhow::Result<()> {
+    let columns = terminal_size::terminal_size()
+        .map(|(terminal_size::Width(width), _)| width as usize)
+        .unwrap_or(DEFAULT_COLUMNS);
+    print!("{}", render_text(&current_state, columns)?);
     Ok(())
 }
 
 fn list_monitors(current_state: get_current_state::Response) -> anyhow::Result<()> {
     let mut table_builder = Builder::new();
     table_builder
-        .push_record(["Connector", "Vendor", "Product name", "Resolution", "Refresh rate", "Scaling", "VRR", "HDR"]);
+        .push_record(["Connector", "Vendor", "Product name", "Resolution", "Refresh rate", "Scaling", "DPI", "VRR", "HDR"]);
     for monitor in current_state.monitors {
         let logical_monitor = current_state.logical_monitors.iter().find(|logical_monitor| logical_monitor.monitors.iter().any(|m| m.connector == monitor.id.connector));
         let scaling = match logical_monitor {
-            Some(logical_monitor) => format!("{:0}%", logical_monitor.scale * 100.0),
+            Some(logical_monitor) => format_scale(logical_monitor.scale),
             None => "".to_string()
         };
         let current_mode = monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false));
+        let dpi = current_mode
+            .and_then(|mode| physical_dpi(&monitor, mode))
+            .map(|dpi| format!("{dpi:.0}"))
+            .unwrap_or_default();
         let vrr_supported = monitor.modes.iter().any(|mode| mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable));
         let (resolution, refresh_rate, vrr_enabled) = match current_mode {
             Some(mode) => {
@@ -168,6 +375,10 @@ fn list_monitors(current_state: get_current_state::Response) -> anyhow::Result<(
             (true, _) => "Supported",
             _ => "No",
         };
+        let vrr = match vrr_range(&monitor, &monitor.modes) {
+            Some(range) if vrr_supported => format!("{vrr} ({range})"),
+            _ => vrr.to_string(),
+        };
         let hdr_supported = monitor.properties.supported_color_modes.is_some_and(|color_modes| color_modes.contains(&MonitorColorMode::BT2100));
         let hdr_enabled = monitor.properties.color_mode.is_some_and(|mode| mode == MonitorColorMode::BT2100);
         let hdr = match (hdr_supported, hdr_enabled) {
@@ -175,7 +386,7 @@ fn list_monitors(current_state: get_current_state::Response) -> anyhow::Result<(
             (true, _) => "Supported",
             _ => "No",
         };
-        table_builder.push_record([monitor.id.connector, monitor.id.vendor, monitor.id.product, resolution, refresh_rate, scaling, vrr.into(), hdr.into()]);
+        table_builder.push_record([monitor.id.connector, monitor.id.vendor, monitor.id.product, resolution, refresh_rate, scaling, dpi, vrr, hdr.into()]);
     }
 
     let mut table = table_builder.build();
@@ -189,38 +400,74 @@ fn list_monitors(current_state: get_current_state::Response) -> anyhow::Result<(
 fn list_modes(current_state: get_current_state::Response, connector: impl AsRef<str>) -> anyhow::Result<()> {
     let mut table_builder = Builder::new();
     table_builder
-        .push_record(["Connector", "Resolutions", "Refresh rates", "Scales"]);
+        .push_record(["Resolution", "Refresh rates", "VRR", "Scales", "Preferred scale", "Mode IDs"]);
     let monitor = current_state.monitors.iter().find(|monitor| monitor.id.connector == connector.as_ref()).ok_or(anyhow!("Could not find a monitor with \"{}\" as a connector", connector.as_ref()))?;
 
-    let mut resolutions = HashSet::new();
-    let mut refresh_rates = HashSet::new();
-    let mut scales = HashSet::new();
-    for mode in &monitor.modes {
-        resolutions.insert((mode.width, mode.height));
-        refresh_rates.insert(mode.refresh_rate.round_ties_even() as u32);
-        for scale in &mode.supported_scales {
-            // Round to a closest quarter
-            scales.insert(format!("{}%", ((scale * 4.0).round() / 4.0 * 100.0) as u32));
+    let mut modes = monitor.modes.clone();
+    modes.sort();
+    modes.reverse();
+
+    // Modes are sorted by resolution first, so all modes with the same resolution are next to each other
+    for resolution_modes in modes.chunk_by(|a, b| a.width == b.width && a.height == b.height) {
+        let first_mode = &resolution_modes[0];
+
+        let refresh_rates: Vec<_> = resolution_modes.iter().map(|mode| {
+            let mut markers = Vec::new();
+            if mode.properties.is_current.unwrap_or(false) {
+                markers.push("current");
+            }
+            if mode.properties.is_preferred.unwrap_or(false) {
+                markers.push("preferred");
+            }
+            if markers.is_empty() {
+                format!("{:.3}", mode.refresh_rate)
+            } else {
+                format!("{:.3} ({})", mode.refresh_rate, markers.join(", "))
+            }
+        }).collect();
+        let mode_ids: Vec<_> = resolution_modes.iter().map(|mode| {
+            let mut flags = Vec::new();
+            if mode.properties.is_interlaced.unwrap_or(false) {
+                flags.push("interlaced");
+            }
+            if mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable) {
+                flags.push("VRR");
+            }
+            if flags.is_empty() {
+                mode.id.clone()
+            } else {
+                format!("{} ({})", mode.id, flags.join(", "))
+            }
+        }).collect();
+        let vrr = match vrr_range(monitor, resolution_modes) {
+            Some(range) => format!("Yes ({range})"),
+            None => "No".to_string(),
+        };
+
+        let mut scales = Vec::new();
+        for mode in resolution_modes {
+            for scale in &mode.supported_scales {
+                let scale = format_scale(*scale);
+                if !scales.contains(&scale) {
+                    scales.push(scale);
+                }
+            }
         }
-    }
+        // Preferred scale is calculated based on resolution, so it's the same for all modes with it
+        let preferred_scale = resolution_modes.iter()
+            .find(|mode| mode.properties.is_preferred.unwrap_or(false))
+            .unwrap_or(first_mode)
+            .preferred_scale;
 
-    let mut resolutions: Vec<_> = resolutions.into_iter().collect();
-    resolutions.sort_by(|a, b| match a.0.cmp(&b.0) {
-        Ordering::Equal => a.1.cmp(&b.1),
-        ord => ord
-    }); 
-    resolutions.reverse();
-    let mut refresh_rates: Vec<_> = refresh_rates.into_iter().collect();
-    refresh_rates.sort();
-    refresh_rates.reverse();
-    let mut scales: Vec<_> = scales.into_iter().collect();
-    scales.sort();
-    table_builder.push_record([
-        connector.as_ref().to_string(), 
-        resolutions.into_iter().map(|(width, height)| format!("{width}x{height}")).collect::<Vec<_>>().join("\n"), 
-        refresh_rates.iter().map(u32::to_string).collect::<Vec<_>>().join("\n"),
-        scales.join("\n"),
-    ]);
+        table_builder.push_record([
+            format!("{}x{}", first_mode.width, first_mode.height),
+            refresh_rates.join("\n"),
+            vrr,
+            scales.join("\n"),
+            format_scale(preferred_scale),
+            mode_ids.join("\n"),
+        ]);
+    }
 
     let mut table = table_builder.build();
     table
@@ -229,3 +476,24 @@ fn list_modes(current_state: get_current_state::Response, connector: impl AsRef<
     println!("{table}");
     Ok(())
 }
+
+/// Formats range of refresh rates that could be used with VRR, e.g. "48-144Hz". Upper bound is
+/// the highest refresh rate of variable modes in `modes`. Returns None if none of them are variable
+fn vrr_range(monitor: &get_current_state::Monitor, modes: &[get_current_state::Mode]) -> Option<String> {
+    let max_refresh_rate = modes.iter()
+        .filter(|mode| mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable))
+        .map(|mode| mode.refresh_rate)
+        .max_by(f64::total_cmp)?;
+    match monitor.properties.min_refresh_rate {
+        Some(min_refresh_rate) => Some(format!("{}-{:.0}Hz", min_refresh_rate, max_refresh_rate)),
+        None => Some(format!("up to {:.0}Hz", max_refresh_rate)),
+    }
+}
+
+/// Formats scale as a factor that could be passed to `set --scale` along with rounded percentage,
+/// e.g. "1.3333 (133%)"
+fn format_scale(scale: f64) -> String {
+    let factor = format!("{scale:.4}");
+    let factor = factor.trim_end_matches('0').trim_end_matches('.');
+    format!("{} ({:.0}%)", factor, scale * 100.0)
+}
@@ -0,0 +1,658 @@
+use std::{collections::BTreeSet, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    dirs,
+    display_config::{
+        apply_monitors_config,
+        get_current_state::{self, LayoutMode, LogicalMonitorTransform, MonitorColorMode, RefreshRateMode},
+    },
+    planner::match_displays,
+};
+
+/// Version of monitors.xml schema that is written by current versions of mutter
+pub const MONITORS_XML_VERSION: u32 = 2;
+
+/// Mutter stores refresh rates rounded to 3 decimal places
+const RATE_TOLERANCE: f64 = 0.001;
+
+/// Root of mutter's monitors.xml, containing stored configuration for each combination of
+/// connected displays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename = "monitors")]
+pub struct MonitorsXml {
+    #[serde(rename = "@version")]
+    pub version: u32,
+    #[serde(rename = "configuration", default)]
+    pub configurations: Vec<Configuration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Configuration {
+    /// either "logical" or "physical"
+    #[serde(rename = "layoutmode", skip_serializing_if = "Option::is_none")]
+    pub layout_mode: Option<String>,
+    #[serde(rename = "logicalmonitor", default)]
+    pub logical_monitors: Vec<LogicalMonitor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<Disabled>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogicalMonitor {
+    pub x: i32,
+    pub y: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    /// either "yes" or "no"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Transform>,
+    #[serde(rename = "monitor", default)]
+    pub monitors: Vec<Monitor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transform {
+    /// one of "normal", "left", "upside_down" or "right"
+    pub rotation: String,
+    /// either "yes" or "no"
+    pub flipped: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Monitor {
+    #[serde(rename = "monitorspec")]
+    pub monitor_spec: MonitorSpec,
+    pub mode: Mode,
+    /// either "yes" or "no"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underscanning: Option<String>,
+    /// either "default" or "bt2100"
+    #[serde(rename = "colormode", skip_serializing_if = "Option::is_none")]
+    pub color_mode: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitorSpec {
+    pub connector: String,
+    pub vendor: String,
+    pub product: String,
+    pub serial: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mode {
+    pub width: i32,
+    pub height: i32,
+    #[serde(serialize_with = "serialize_rate")]
+    pub rate: f64,
+    /// "interlace" for interlaced modes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag: Option<String>,
+    /// either "fixed" or "variable"
+    #[serde(rename = "refreshratemode", skip_serializing_if = "Option::is_none")]
+    pub refresh_rate_mode: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Disabled {
+    #[serde(rename = "monitorspec", default)]
+    pub monitor_specs: Vec<MonitorSpec>,
+}
+
+fn serialize_rate<S: Serializer>(rate: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{rate:.3}"))
+}
+
+impl From<&get_current_state::MonitorId> for MonitorSpec {
+    fn from(id: &get_current_state::MonitorId) -> Self {
+        Self {
+            connector: id.connector.clone(),
+            vendor: id.vendor.clone(),
+            product: id.product.clone(),
+            serial: id.serial.clone(),
+        }
+    }
+}
+
+impl From<&MonitorSpec> for get_current_state::MonitorId {
+    fn from(spec: &MonitorSpec) -> Self {
+        Self {
+            connector: spec.connector.clone(),
+            vendor: spec.vendor.clone(),
+            product: spec.product.clone(),
+            serial: spec.serial.clone(),
+        }
+    }
+}
+
+impl MonitorSpec {
+    fn matches(&self, id: &get_current_state::MonitorId) -> bool {
+        self.connector == id.connector && self.vendor == id.vendor && self.product == id.product && self.serial == id.serial
+    }
+}
+
+impl MonitorsXml {
+    pub fn parse(xml: &str) -> anyhow::Result<Self> {
+        let monitors_xml: Self = quick_xml::de::from_str(xml)?;
+        if monitors_xml.version != MONITORS_XML_VERSION {
+            return Err(anyhow!("unsupported monitors.xml version {}, only version {} is supported", monitors_xml.version, MONITORS_XML_VERSION));
+        }
+        Ok(monitors_xml)
+    }
+
+    pub fn to_xml(&self) -> anyhow::Result<String> {
+        let mut xml = String::new();
+        let mut serializer = quick_xml::se::Serializer::new(&mut xml);
+        serializer.indent(' ', 2);
+        self.serialize(serializer)?;
+        xml.push('\n');
+        Ok(xml)
+    }
+
+    /// Finds configuration that mutter would use for currently connected displays
+    pub fn matching_configuration(&self, current_state: &get_current_state::Response) -> Option<(usize, &Configuration)> {
+        self.configurations.iter().enumerate()
+            .find(|(_, configuration)| configuration.matches(current_state))
+    }
+}
+
+/// Default location of monitors.xml, which is read by mutter on startup
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    Ok(dirs::config_dir()?.join("monitors.xml"))
+}
+
+impl Configuration {
+    /// Converts current state into a configuration in the same way mutter stores it
+    pub fn from_state(current_state: &get_current_state::Response) -> anyhow::Result<Self> {
+        let logical_monitors = current_state.logical_monitors.iter().map(|logical_monitor| {
+            let monitors = logical_monitor.monitors.iter().map(|monitor_id| {
+                let monitor = current_state.monitors.iter()
+                    .find(|monitor| &monitor.id == monitor_id)
+                    .ok_or(anyhow!("could not find a display with \"{}\" connector name", monitor_id.connector))?;
+                let mode = monitor.modes.iter()
+                    .find(|mode| mode.properties.is_current.unwrap_or(false))
+                    .ok_or(anyhow!("could not find current configuration of \"{}\"", monitor_id.connector))?;
+                Ok(Monitor {
+                    monitor_spec: monitor_id.into(),
+                    mode: Mode {
+                        width: mode.width,
+                        height: mode.height,
+                        rate: mode.refresh_rate,
+                        flag: mode.properties.is_interlaced.unwrap_or(false).then(|| "interlace".to_string()),
+                        refresh_rate_mode: mode.properties.refresh_rate_mode
+                            .filter(|rate_mode| *rate_mode == RefreshRateMode::Variable)
+                            .map(|_| "variable".to_string()),
+                    },
+                    underscanning: monitor.properties.is_underscanning.filter(|f| *f).map(|_| "yes".to_string()),
+                    color_mode: monitor.properties.color_mode
+                        .filter(|color_mode| *color_mode == MonitorColorMode::BT2100)
+                        .map(|_| "bt2100".to_string()),
+                })
+            }).collect::<anyhow::Result<_>>()?;
+            Ok(LogicalMonitor {
+                x: logical_monitor.x,
+                y: logical_monitor.y,
+                scale: Some(logical_monitor.scale),
+                primary: logical_monitor.primary.then(|| "yes".to_string()),
+                transform: transform_to_xml(logical_monitor.transform),
+                monitors,
+            })
+        }).collect::<anyhow::Result<_>>()?;
+
+        let disabled: Vec<MonitorSpec> = current_state.monitors.iter()
+            .filter(|monitor| !current_state.logical_monitors.iter().any(|logical_monitor| logical_monitor.monitors.contains(&monitor.id)))
+            .map(|monitor| (&monitor.id).into())
+            .collect();
+
+        Ok(Self {
+            layout_mode: current_state.properties.layout_mode.map(|layout_mode| match layout_mode {
+                LayoutMode::Logical => "logical".to_string(),
+                LayoutMode::Physical => "physical".to_string(),
+            }),
+            logical_monitors,
+            disabled: (!disabled.is_empty()).then_some(Disabled { monitor_specs: disabled }),
+        })
+    }
+
+    fn monitor_specs(&self) -> impl Iterator<Item = &MonitorSpec> {
+        self.logical_monitors.iter()
+            .flat_map(|logical_monitor| logical_monitor.monitors.iter().map(|monitor| &monitor.monitor_spec))
+            .chain(self.disabled.iter().flat_map(|disabled| disabled.monitor_specs.iter()))
+    }
+
+    /// Configuration is used by mutter only if it describes exactly the same set of displays
+    pub fn matches(&self, current_state: &get_current_state::Response) -> bool {
+        let specs: Vec<_> = self.monitor_specs().collect();
+        specs.len() == current_state.monitors.len()
+            && current_state.monitors.iter().all(|monitor| specs.iter().any(|spec| spec.matches(&monitor.id)))
+    }
+
+    pub fn layout_mode(&self) -> anyhow::Result<Option<LayoutMode>> {
+        match self.layout_mode.as_deref() {
+            None => Ok(None),
+            Some("logical") => Ok(Some(LayoutMode::Logical)),
+            Some("physical") => Ok(Some(LayoutMode::Physical)),
+            Some(layout_mode) => Err(anyhow!("unknown layout mode \"{layout_mode}\"")),
+        }
+    }
+
+    /// Human readable summary of displays, e.g. "DP-1 (DEL U2720Q), eDP-1 (BOE 0x0bca, disabled)"
+    pub fn describe(&self) -> String {
+        let enabled = self.logical_monitors.iter()
+            .flat_map(|logical_monitor| logical_monitor.monitors.iter())
+            .map(|monitor| format!("{} ({} {})", monitor.monitor_spec.connector, monitor.monitor_spec.vendor, monitor.monitor_spec.product));
+        let disabled = self.disabled.iter()
+            .flat_map(|disabled| disabled.monitor_specs.iter())
+            .map(|spec| format!("{} ({} {}, disabled)", spec.connector, spec.vendor, spec.product));
+        enabled.chain(disabled).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Builds a config for ApplyMonitorsConfig. Displays are matched by vendor, product and serial,
+    /// so that configuration could be applied even if displays are connected to other ports.
+    pub fn plan(&self, current_state: &get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+        let specs: Vec<_> = self.logical_monitors.iter()
+            .flat_map(|logical_monitor| &logical_monitor.monitors)
+            .map(|xml_monitor| (&xml_monitor.monitor_spec).into())
+            .collect();
+        let matched = match_displays(current_state, &specs);
+        self.logical_monitors.iter().map(|logical_monitor| {
+            let transform = transform_from_xml(logical_monitor.transform.as_ref())?;
+            let monitors = logical_monitor.monitors.iter().map(|xml_monitor| {
+                let spec = &xml_monitor.monitor_spec;
+                let monitor = matched.get(&spec.connector)
+                    .ok_or(anyhow!("display {} {} ({}) is not connected", spec.vendor, spec.product, spec.connector))?;
+                let mode = xml_monitor.mode.find(monitor)?;
+                let color_mode = match xml_monitor.color_mode.as_deref() {
+                    None | Some("default") => MonitorColorMode::Default,
+                    Some("bt2100") => MonitorColorMode::BT2100,
+                    Some(color_mode) => return Err(anyhow!("unknown color mode \"{color_mode}\" for \"{}\"", spec.connector)),
+                };
+                let hdr_supported = monitor.properties.supported_color_modes.as_ref().is_some_and(|modes| modes.contains(&MonitorColorMode::BT2100));
+                if color_mode == MonitorColorMode::BT2100 && !hdr_supported {
+                    return Err(anyhow!("display \"{}\" does not support HDR", monitor.id.connector));
+                }
+                Ok(apply_monitors_config::Monitor {
+                    connector: monitor.id.connector.clone(),
+                    mode: mode.id.clone(),
+                    properties: apply_monitors_config::MonitorProperties {
+                        underscanning: xml_monitor.underscanning.as_deref().map(|underscanning| underscanning == "yes"),
+                        color_mode: monitor.properties.supported_color_modes.as_ref().map(|_| color_mode),
+                    },
+                })
+            }).collect::<anyhow::Result<Vec<_>>>()?;
+            let scale = logical_monitor.scale.unwrap_or(1.0);
+            Ok(apply_monitors_config::LogicalMonitor {
+                x: logical_monitor.x,
+                y: logical_monitor.y,
+                scale,
+                transform,
+                primary: logical_monitor.primary.as_deref() == Some("yes"),
+                monitors,
+            })
+        }).collect()
+    }
+
+    /// Checks for problems that could be detected without connected displays
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(err) = self.layout_mode() {
+            problems.push(err.to_string());
+        }
+        let primary_count = self.logical_monitors.iter()
+            .filter(|logical_monitor| logical_monitor.primary.as_deref() == Some("yes"))
+            .count();
+        if primary_count != 1 {
+            problems.push(format!("expected exactly one primary logical monitor, found {primary_count}"));
+        }
+        for logical_monitor in &self.logical_monitors {
+            let position = format!("logical monitor at {},{}", logical_monitor.x, logical_monitor.y);
+            if let Err(err) = transform_from_xml(logical_monitor.transform.as_ref()) {
+                problems.push(format!("{position}: {err}"));
+            }
+            if logical_monitor.scale.is_some_and(|scale| scale <= 0.0) {
+                problems.push(format!("{position}: scale should be positive"));
+            }
+            if logical_monitor.monitors.is_empty() {
+                problems.push(format!("{position}: does not have any displays"));
+            }
+            for monitor in &logical_monitor.monitors {
+                let connector = &monitor.monitor_spec.connector;
+                if !matches!(monitor.color_mode.as_deref(), None | Some("default") | Some("bt2100")) {
+                    problems.push(format!("{connector}: unknown color mode \"{}\"", monitor.color_mode.as_deref().unwrap_or_default()));
+                }
+                if !matches!(monitor.mode.refresh_rate_mode.as_deref(), None | Some("fixed") | Some("variable")) {
+                    problems.push(format!("{connector}: unknown refresh rate mode \"{}\"", monitor.mode.refresh_rate_mode.as_deref().unwrap_or_default()));
+                }
+            }
+        }
+        problems
+    }
+}
+
+impl Mode {
+    fn find<'a>(&self, monitor: &'a get_current_state::Monitor) -> anyhow::Result<&'a get_current_state::Mode> {
+        let refresh_rate_mode = match self.refresh_rate_mode.as_deref() {
+            None | Some("fixed") => RefreshRateMode::Fixed,
+            Some("variable") => RefreshRateMode::Variable,
+            Some(rate_mode) => return Err(anyhow!("unknown refresh rate mode \"{rate_mode}\" for \"{}\"", monitor.id.connector)),
+        };
+        let interlaced = self.flag.as_deref() == Some("interlace");
+        monitor.modes.iter()
+            .find(|mode| {
+                mode.width == self.width && mode.height == self.height
+                    && (mode.refresh_rate - self.rate).abs() < RATE_TOLERANCE
+                    && mode.properties.refresh_rate_mode.unwrap_or_default() == refresh_rate_mode
+                    && mode.properties.is_interlaced.unwrap_or(false) == interlaced
+            })
+            .ok_or(anyhow!("display \"{}\" does not support {}x{}@{:.3} mode", monitor.id.connector, self.width, self.height, self.rate))
+    }
+}
+
+fn transform_to_xml(transform: LogicalMonitorTransform) -> Option<Transform> {
+    let rotation = match transform as u32 % 4 {
+        1 => "left",
+        2 => "upside_down",
+        3 => "right",
+        _ => "normal",
+    };
+    let flipped = transform as u32 >= 4;
+    // Mutter omits transform for normal orientation
+    (transform != LogicalMonitorTransform::Normal).then(|| Transform {
+        rotation: rotation.to_string(),
+        flipped: if flipped { "yes" } else { "no" }.to_string(),
+    })
+}
+
+fn transform_from_xml(transform: Option<&Transform>) -> anyhow::Result<LogicalMonitorTransform> {
+    let Some(transform) = transform else {
+        return Ok(LogicalMonitorTransform::Normal);
+    };
+    let flipped = transform.flipped == "yes";
+    Ok(match (transform.rotation.as_str(), flipped) {
+        ("normal", false) => LogicalMonitorTransform::Normal,
+        ("left", false) => LogicalMonitorTransform::Turned90Deg,
+        ("upside_down", false) => LogicalMonitorTransform::Turned180Deg,
+        ("right", false) => LogicalMonitorTransform::Turned270Deg,
+        ("normal", true) => LogicalMonitorTransform::Flipped,
+        ("left", true) => LogicalMonitorTransform::Turned90DegFlipped,
+        ("upside_down", true) => LogicalMonitorTransform::Turned180DegFlipped,
+        ("right", true) => LogicalMonitorTransform::Turned270DegFlipped,
+        (rotation, _) => return Err(anyhow!("unknown rotation \"{rotation}\"")),
+    })
+}
+
+/// Reads and parses monitors.xml from `path`
+pub fn read(path: &std::path::Path) -> anyhow::Result<MonitorsXml> {
+    let xml = std::fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    MonitorsXml::parse(&xml).with_context(|| format!("could not parse {}", path.display()))
+}
+
+/// Reads monitors.xml that is going to be rewritten. Fails if it has elements that are not
+/// modelled here, e.g. mutter's `<policy>`, since they would be lost.
+pub fn read_for_update(path: &std::path::Path) -> anyhow::Result<MonitorsXml> {
+    let xml = std::fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let monitors_xml = MonitorsXml::parse(&xml).with_context(|| format!("could not parse {}", path.display()))?;
+    let kept = element_paths(&monitors_xml.to_xml()?)?;
+    let dropped: Vec<_> = element_paths(&xml)?.difference(&kept).cloned().collect();
+    // Only report outermost elements, their children are dropped along with them
+    let dropped: Vec<_> = dropped.iter()
+        .filter(|path| !dropped.iter().any(|parent| path.starts_with(&format!("{parent}/"))))
+        .map(|path| format!("<{path}>"))
+        .collect();
+    if !dropped.is_empty() {
+        return Err(anyhow!("{} has elements that would be lost when rewriting it: {}", path.display(), dropped.join(", ")));
+    }
+    Ok(monitors_xml)
+}
+
+/// Lists paths of all elements in `xml`, e.g. "monitors/configuration/layoutmode".
+fn element_paths(xml: &str) -> anyhow::Result<BTreeSet<String>> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut path = Vec::new();
+    let mut paths = BTreeSet::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                path.push(String::from_utf8_lossy(element.name().as_ref()).into_owned());
+                paths.insert(path.join("/"));
+            },
+            Event::Empty(element) => {
+                paths.insert(format!("{}/{}", path.join("/"), String::from_utf8_lossy(element.name().as_ref())));
+            },
+            Event::End(_) => {
+                path.pop();
+            },
+            Event::Eof => return Ok(paths),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, mode, monitor, monitor_with_modes, state, vrr_mode};
+
+    /// Formatted the same way as mutter writes it
+    const LAPTOP_WITH_DISPLAY: &str = r#"<monitors version="2">
+  <configuration>
+    <layoutmode>logical</layoutmode>
+    <logicalmonitor>
+      <x>0</x>
+      <y>0</y>
+      <scale>1.25</scale>
+      <primary>yes</primary>
+      <transform>
+        <rotation>left</rotation>
+        <flipped>no</flipped>
+      </transform>
+      <monitor>
+        <monitorspec>
+          <connector>DP-1</connector>
+          <vendor>GSM</vendor>
+          <product>Display DP-1</product>
+          <serial>DP-1-serial</serial>
+        </monitorspec>
+        <mode>
+          <width>2560</width>
+          <height>1440</height>
+          <rate>59.951</rate>
+          <refreshratemode>variable</refreshratemode>
+        </mode>
+        <colormode>bt2100</colormode>
+      </monitor>
+    </logicalmonitor>
+    <disabled>
+      <monitorspec>
+        <connector>eDP-1</connector>
+        <vendor>GSM</vendor>
+        <product>Display eDP-1</product>
+        <serial>eDP-1-serial</serial>
+      </monitorspec>
+    </disabled>
+  </configuration>
+</monitors>
+"#;
+
+    fn laptop_with_display() -> get_current_state::Response {
+        state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.0, &["DP-1"])],
+        )
+    }
+
+    fn xml_mode(width: i32, height: i32, rate: f64) -> Mode {
+        Mode { width, height, rate, flag: None, refresh_rate_mode: None }
+    }
+
+    #[test]
+    fn parsed_file_is_written_back_unchanged() {
+        let monitors_xml = MonitorsXml::parse(LAPTOP_WITH_DISPLAY).unwrap();
+        let configuration = &monitors_xml.configurations[0];
+        assert_eq!(configuration.layout_mode().unwrap(), Some(LayoutMode::Logical));
+        assert_eq!(configuration.logical_monitors[0].scale, Some(1.25));
+        assert_eq!(configuration.logical_monitors[0].monitors[0].mode.rate, 59.951);
+        assert_eq!(configuration.describe(), "DP-1 (GSM Display DP-1), eDP-1 (GSM Display eDP-1, disabled)");
+        assert_eq!(monitors_xml.to_xml().unwrap(), LAPTOP_WITH_DISPLAY);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        assert!(MonitorsXml::parse(&LAPTOP_WITH_DISPLAY.replace(r#"version="2""#, r#"version="1""#)).is_err());
+    }
+
+    #[test]
+    fn transforms_are_converted_both_ways() {
+        use LogicalMonitorTransform::*;
+        assert!(transform_to_xml(Normal).is_none());
+        for transform in [Normal, Turned90Deg, Turned180Deg, Turned270Deg, Flipped, Turned90DegFlipped, Turned180DegFlipped, Turned270DegFlipped] {
+            assert_eq!(transform_from_xml(transform_to_xml(transform).as_ref()).unwrap(), transform);
+        }
+        let transform = transform_to_xml(Turned270DegFlipped).unwrap();
+        assert_eq!((transform.rotation.as_str(), transform.flipped.as_str()), ("right", "yes"));
+        let sideways = Transform { rotation: "sideways".to_string(), flipped: "no".to_string() };
+        assert!(transform_from_xml(Some(&sideways)).is_err());
+    }
+
+    #[test]
+    fn modes_are_found_by_rate_refresh_rate_mode_and_interlacing() {
+        let mut interlaced = mode(1920, 1080, 60.0);
+        interlaced.id.push('i');
+        interlaced.properties.is_interlaced = Some(true);
+        let display = monitor_with_modes("DP-1", vec![
+            mode(1920, 1080, 60.0),
+            mode(1920, 1080, 59.951),
+            vrr_mode(1920, 1080, 59.951),
+            interlaced,
+        ]);
+        assert_eq!(xml_mode(1920, 1080, 59.951).find(&display).unwrap().id, "1920x1080@59.951");
+        assert_eq!(xml_mode(1920, 1080, 59.9514).find(&display).unwrap().id, "1920x1080@59.951");
+        assert!(xml_mode(1920, 1080, 59.94).find(&display).is_err());
+        assert!(xml_mode(1280, 720, 60.0).find(&display).is_err());
+
+        let variable = Mode { refresh_rate_mode: Some("variable".to_string()), ..xml_mode(1920, 1080, 59.951) };
+        assert_eq!(variable.find(&display).unwrap().id, "1920x1080@59.951+vrr");
+        let fixed = Mode { refresh_rate_mode: Some("fixed".to_string()), ..xml_mode(1920, 1080, 59.951) };
+        assert_eq!(fixed.find(&display).unwrap().id, "1920x1080@59.951");
+        let unknown = Mode { refresh_rate_mode: Some("adaptive".to_string()), ..xml_mode(1920, 1080, 59.951) };
+        assert!(unknown.find(&display).is_err());
+
+        let interlaced = Mode { flag: Some("interlace".to_string()), ..xml_mode(1920, 1080, 60.0) };
+        assert_eq!(interlaced.find(&display).unwrap().id, "1920x1080@60.000i");
+        assert_eq!(xml_mode(1920, 1080, 60.0).find(&display).unwrap().id, "1920x1080@60.000");
+    }
+
+    #[test]
+    fn configuration_matches_exactly_the_same_displays() {
+        let current_state = laptop_with_display();
+        let mut monitors_xml = MonitorsXml::parse(LAPTOP_WITH_DISPLAY).unwrap();
+        let mut docked = monitors_xml.configurations[0].clone();
+        assert!(docked.matches(&current_state));
+
+        let laptop_only = Configuration::from_state(&state(vec![monitor("eDP-1", &[(1920, 1200)])], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])])).unwrap();
+        assert!(!laptop_only.matches(&current_state));
+
+        // Mutter does not use configurations for displays connected to other ports
+        docked.logical_monitors[0].monitors[0].monitor_spec.connector = "HDMI-1".to_string();
+        assert!(!docked.matches(&current_state));
+
+        monitors_xml.configurations.insert(0, laptop_only);
+        monitors_xml.configurations.push(docked);
+        let (index, _) = monitors_xml.matching_configuration(&current_state).unwrap();
+        assert_eq!(index, 1);
+        monitors_xml.configurations.remove(1);
+        assert!(monitors_xml.matching_configuration(&current_state).is_none());
+    }
+
+    #[test]
+    fn current_state_is_planned_back_unchanged() {
+        let current_state = laptop_with_display();
+        let configuration = Configuration::from_state(&current_state).unwrap();
+        assert!(configuration.matches(&current_state));
+        assert!(configuration.problems().is_empty());
+        let planned = configuration.plan(&current_state).unwrap();
+        let summary: Vec<_> = planned.iter()
+            .map(|logical_monitor| (logical_monitor.x, logical_monitor.y, logical_monitor.primary, logical_monitor.monitors[0].mode.as_str()))
+            .collect();
+        assert_eq!(summary, [(0, 0, true, "1920x1200@60.000"), (1920, 0, false, "2560x1440@60.000")]);
+    }
+
+    #[test]
+    fn displays_are_found_on_other_ports() {
+        let mut moved = monitor("DP-2", &[(2560, 1440)]);
+        moved.id = get_current_state::MonitorId { connector: "DP-2".to_string(), ..monitor("DP-1", &[]).id };
+        let current_state = state(vec![monitor("eDP-1", &[(1920, 1200)]), moved], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let configuration = Configuration::from_state(&laptop_with_display()).unwrap();
+        assert!(!configuration.matches(&current_state));
+        let planned = configuration.plan(&current_state).unwrap();
+        assert_eq!(planned[1].monitors[0].connector, "DP-2");
+
+        let current_state = state(vec![monitor("eDP-1", &[(1920, 1200)])], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let err = configuration.plan(&current_state).unwrap_err();
+        assert_eq!(err.to_string(), "display GSM Display DP-1 (DP-1) is not connected");
+    }
+
+    #[test]
+    fn identical_displays_on_other_ports_are_used_once() {
+        let identical = |connector: &str| {
+            let mut display = monitor(connector, &[(1920, 1080)]);
+            display.id = get_current_state::MonitorId { connector: connector.to_string(), ..monitor("DP-1", &[]).id };
+            display
+        };
+        let saved = state(vec![identical("DP-1"), identical("DP-2")], vec![logical_monitor(0, 0, 1.0, &["DP-1"]), logical_monitor(1920, 0, 1.0, &["DP-2"])]);
+        let configuration = Configuration::from_state(&saved).unwrap();
+        let current_state = state(vec![identical("DP-2"), identical("DP-3")], vec![logical_monitor(0, 0, 1.0, &["DP-2"])]);
+        let connectors: Vec<_> = configuration.plan(&current_state).unwrap().iter()
+            .map(|logical_monitor| logical_monitor.monitors[0].connector.clone())
+            .collect();
+        assert_eq!(connectors, ["DP-3", "DP-2"]);
+    }
+
+    #[test]
+    fn unmodelled_elements_are_not_dropped_when_updating() {
+        let path = std::env::temp_dir().join(format!("monitors-xml-test-{}.xml", std::process::id()));
+        std::fs::write(&path, LAPTOP_WITH_DISPLAY).unwrap();
+        assert!(read_for_update(&path).is_ok());
+
+        let with_policy = LAPTOP_WITH_DISPLAY.replace("  <configuration>", "  <policy>\n    <stores>\n      <store>system</store>\n    </stores>\n  </policy>\n  <configuration>");
+        std::fs::write(&path, with_policy).unwrap();
+        let result = read_for_update(&path);
+        std::fs::remove_file(&path).unwrap();
+        let err = result.unwrap_err().to_string();
+        assert!(err.ends_with("has elements that would be lost when rewriting it: <monitors/policy>"), "{err}");
+    }
+
+    #[test]
+    fn problems_are_reported_without_displays() {
+        let mut configuration = MonitorsXml::parse(LAPTOP_WITH_DISPLAY).unwrap().configurations.remove(0);
+        assert!(configuration.problems().is_empty());
+
+        configuration.layout_mode = Some("stretched".to_string());
+        let logical_monitor = &mut configuration.logical_monitors[0];
+        logical_monitor.scale = Some(0.0);
+        logical_monitor.transform.as_mut().unwrap().rotation = "sideways".to_string();
+        logical_monitor.monitors[0].color_mode = Some("hdr10".to_string());
+        logical_monitor.monitors[0].mode.refresh_rate_mode = Some("adaptive".to_string());
+        let mut empty = logical_monitor.clone();
+        empty.x = 2048;
+        empty.transform = None;
+        empty.scale = None;
+        empty.monitors.clear();
+        configuration.logical_monitors.push(empty);
+        assert_eq!(configuration.problems(), [
+            "unknown layout mode \"stretched\"",
+            "expected exactly one primary logical monitor, found 2",
+            "logical monitor at 0,0: unknown rotation \"sideways\"",
+            "logical monitor at 0,0: scale should be positive",
+            "DP-1: unknown color mode \"hdr10\"",
+            "DP-1: unknown refresh rate mode \"adaptive\"",
+            "logical monitor at 2048,0: does not have any displays",
+        ]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::future::Future;
+
+use anyhow::anyhow;
+
+use crate::{cli::NightLightCommand, display_config::DisplayConfigProxy, gsettings};
+
+/// GSettings schema of GNOME's color plugin, which owns night light settings.
+const COLOR_SCHEMA: &str = "org.gnome.settings-daemon.plugins.color";
+
+/// Range of color temperatures accepted by GNOME's color plugin, in Kelvin.
+pub const MIN_TEMPERATURE: u32 = 1700;
+pub const MAX_TEMPERATURE: u32 = 4700;
+
+#[zbus::proxy(
+    default_service = "org.gnome.SettingsDaemon.Color",
+    default_path = "/org/gnome/SettingsDaemon/Color",
+    interface = "org.gnome.SettingsDaemon.Color"
+)]
+pub trait Color {
+    /// NightLightActive property
+    #[zbus(property)]
+    fn night_light_active(&self) -> zbus::Result<bool>;
+
+    /// Temperature property
+    #[zbus(property)]
+    fn temperature(&self) -> zbus::Result<u32>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Follows sunset and sunrise at current location
+    SunsetToSunrise,
+    /// Fixed hours, e.g. 20.5 for 20:30
+    Manual { from: f64, to: f64 },
+}
+
+/// Night light configuration, as stored in settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub enabled: bool,
+    pub temperature: u32,
+    pub schedule: Schedule,
+}
+
+/// Night light state as reported by running color plugin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub active: bool,
+    pub temperature: u32,
+}
+
+/// Source of night light settings and state, so that commands could be run against something
+/// other than a GNOME session.
+pub trait NightLightBackend {
+    /// Whether mutter is able to change color temperature of displays
+    fn supported(&self) -> impl Future<Output = anyhow::Result<bool>>;
+    fn settings(&self) -> impl Future<Output = anyhow::Result<Settings>>;
+    /// Returns `None` if color plugin is not running
+    fn status(&self) -> impl Future<Output = anyhow::Result<Option<Status>>>;
+    fn set_enabled(&mut self, enabled: bool) -> impl Future<Output = anyhow::Result<()>>;
+    fn set_temperature(&mut self, temperature: u32) -> impl Future<Output = anyhow::Result<()>>;
+    fn set_schedule(&mut self, schedule: Schedule) -> impl Future<Output = anyhow::Result<()>>;
+}
+
+/// Reads settings through `gsettings` and state from GNOME settings daemon.
+pub struct GnomeBackend<'a> {
+    display_config: &'a DisplayConfigProxy<'a>,
+    color: Option<ColorProxy<'a>>,
+}
+
+impl<'a> GnomeBackend<'a> {
+    pub async fn new(conn: &zbus::Connection, display_config: &'a DisplayConfigProxy<'a>) -> Self {
+        Self {
+            display_config,
+            color: ColorProxy::new(conn).await.ok(),
+        }
+    }
+}
+
+async fn get_setting<T: std::str::FromStr>(key: &str) -> anyhow::Result<T> {
+    gsettings::get(COLOR_SCHEMA, key).await
+}
+
+async fn set_setting(key: &str, value: &str) -> anyhow::Result<()> {
+    gsettings::set(COLOR_SCHEMA, key, value).await
+}
+
+impl NightLightBackend for GnomeBackend<'_> {
+    async fn supported(&self) -> anyhow::Result<bool> {
+        Ok(self.display_config.night_light_supported().await?)
+    }
+
+    async fn settings(&self) -> anyhow::Result<Settings> {
+        let schedule = if get_setting("night-light-schedule-automatic").await? {
+            Schedule::SunsetToSunrise
+        } else {
+            Schedule::Manual {
+                from: get_setting("night-light-schedule-from").await?,
+                to: get_setting("night-light-schedule-to").await?,
+            }
+        };
+        Ok(Settings {
+            enabled: get_setting("night-light-enabled").await?,
+            temperature: get_setting("night-light-temperature").await?,
+            schedule,
+        })
+    }
+
+    async fn status(&self) -> anyhow::Result<Option<Status>> {
+        let Some(color) = &self.color else {
+            return Ok(None);
+        };
+        match (color.night_light_active().await, color.temperature().await) {
+            (Ok(active), Ok(temperature)) => Ok(Some(Status { active, temperature })),
+            // Proxy is created lazily, so missing service is only noticed when reading properties
+            _ => Ok(None),
+        }
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        set_setting("night-light-enabled", &enabled.to_string()).await
+    }
+
+    async fn set_temperature(&mut self, temperature: u32) -> anyhow::Result<()> {
+        set_setting("night-light-temperature", &temperature.to_string()).await
+    }
+
+    async fn set_schedule(&mut self, schedule: Schedule) -> anyhow::Result<()> {
+        match schedule {
+            Schedule::SunsetToSunrise => set_setting("night-light-schedule-automatic", "true").await,
+            Schedule::Manual { from, to } => {
+                set_setting("night-light-schedule-from", &from.to_string()).await?;
+                set_setting("night-light-schedule-to", &to.to_string()).await?;
+                set_setting("night-light-schedule-automatic", "false").await
+            },
+        }
+    }
+}
+
+/// Keeps everything in memory, for running commands without a session.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    pub supported: bool,
+    pub settings: Settings,
+    pub status: Option<Status>,
+}
+
+impl NightLightBackend for MemoryBackend {
+    async fn supported(&self) -> anyhow::Result<bool> {
+        Ok(self.supported)
+    }
+
+    async fn settings(&self) -> anyhow::Result<Settings> {
+        Ok(self.settings.clone())
+    }
+
+    async fn status(&self) -> anyhow::Result<Option<Status>> {
+        Ok(self.status.clone())
+    }
+
+    async fn set_enabled(&mut self, enabled: bool) -> anyhow::Result<()> {
+        self.settings.enabled = enabled;
+        Ok(())
+    }
+
+    async fn set_temperature(&mut self, temperature: u32) -> anyhow::Result<()> {
+        self.settings.temperature = temperature;
+        Ok(())
+    }
+
+    async fn set_schedule(&mut self, schedule: Schedule) -> anyhow::Result<()> {
+        self.settings.schedule = schedule;
+        Ok(())
+    }
+}
+
+/// Formats hours as time of day, e.g. 20.5 as 20:30.
+pub fn format_hours(hours: f64) -> String {
+    let minutes = (hours * 60.0).round() as u32;
+    format!("{:02}:{:02}", minutes / 60 % 24, minutes % 60)
+}
+
+/// Runs `night-light` command against `backend`, returning text that should be shown to user.
+pub async fn execute<B: NightLightBackend>(backend: &mut B, command: &NightLightCommand) -> anyhow::Result<String> {
+    // Settings could always be read or night light turned off, other changes would have no effect
+    let needs_support = !matches!(command, NightLightCommand::Status | NightLightCommand::Off);
+    if needs_support && !backend.supported().await? {
+        return Err(anyhow!("night light is not supported by mutter, e.g. because displays do not support gamma adjustments"));
+    }
+    match command {
+        NightLightCommand::Status => {
+            let settings = backend.settings().await?;
+            let schedule = match settings.schedule {
+                Schedule::SunsetToSunrise => "sunset to sunrise".to_string(),
+                Schedule::Manual { from, to } => format!("{} to {}", format_hours(from), format_hours(to)),
+            };
+            let mut status = format!(
+                "Enabled: {}\nTemperature: {}K\nSchedule: {schedule}\n",
+                if settings.enabled { "yes" } else { "no" },
+                settings.temperature,
+            );
+            match backend.status().await? {
+                _ if !backend.supported().await? => status += "Active: no, displays do not support gamma adjustments\n",
+                Some(Status { active: true, temperature }) => status += &format!("Active: yes, currently {temperature}K\n"),
+                Some(Status { active: false, .. }) => status += "Active: no\n",
+                None => status += "Active: unknown, GNOME settings daemon is not running\n",
+            }
+            Ok(status)
+        },
+        NightLightCommand::On => backend.set_enabled(true).await.map(|_| String::new()),
+        NightLightCommand::Off => backend.set_enabled(false).await.map(|_| String::new()),
+        NightLightCommand::Temperature(args) => {
+            if !(MIN_TEMPERATURE..=MAX_TEMPERATURE).contains(&args.kelvin) {
+                return Err(anyhow!("temperature should be between {MIN_TEMPERATURE}K and {MAX_TEMPERATURE}K"));
+            }
+            backend.set_temperature(args.kelvin).await.map(|_| String::new())
+        },
+        NightLightCommand::Schedule(args) => {
+            let schedule = match (args.from, args.to) {
+                (Some(from), Some(to)) => Schedule::Manual { from, to },
+                _ => Schedule::SunsetToSunrise,
+            };
+            backend.set_schedule(schedule).await.map(|_| String::new())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{NightLightScheduleArgs, NightLightTemperatureArgs};
+
+    fn backend() -> MemoryBackend {
+        MemoryBackend {
+            supported: true,
+            settings: Settings {
+                enabled: true,
+                temperature: 2700,
+                schedule: Schedule::Manual { from: 20.5, to: 6.0 },
+            },
+            status: Some(Status { active: true, temperature: 3100 }),
+        }
+    }
+
+    #[tokio::test]
+    async fn status_describes_settings_and_state() {
+        let output = execute(&mut backend(), &NightLightCommand::Status).await.unwrap();
+        assert_eq!(output, "Enabled: yes\nTemperature: 2700K\nSchedule: 20:30 to 06:00\nActive: yes, currently 3100K\n");
+    }
+
+    #[tokio::test]
+    async fn status_without_settings_daemon() {
+        let mut backend = MemoryBackend { status: None, ..backend() };
+        let output = execute(&mut backend, &NightLightCommand::Status).await.unwrap();
+        assert!(output.ends_with("Active: unknown, GNOME settings daemon is not running\n"));
+    }
+
+    #[tokio::test]
+    async fn temperature_outside_of_range_is_rejected() {
+        let mut backend = backend();
+        for kelvin in [MIN_TEMPERATURE - 1, MAX_TEMPERATURE + 1] {
+            let command = NightLightCommand::Temperature(NightLightTemperatureArgs { kelvin });
+            assert!(execute(&mut backend, &command).await.is_err());
+        }
+        assert_eq!(backend.settings.temperature, 2700);
+
+        let command = NightLightCommand::Temperature(NightLightTemperatureArgs { kelvin: MAX_TEMPERATURE });
+        execute(&mut backend, &command).await.unwrap();
+        assert_eq!(backend.settings.temperature, MAX_TEMPERATURE);
+    }
+
+    #[tokio::test]
+    async fn schedule_is_manual_only_with_both_times() {
+        let mut backend = backend();
+        let command = NightLightCommand::Schedule(NightLightScheduleArgs { from: None, to: None });
+        execute(&mut backend, &command).await.unwrap();
+        assert_eq!(backend.settings.schedule, Schedule::SunsetToSunrise);
+
+        let command = NightLightCommand::Schedule(NightLightScheduleArgs { from: Some(22.0), to: Some(7.25) });
+        execute(&mut backend, &command).await.unwrap();
+        assert_eq!(backend.settings.schedule, Schedule::Manual { from: 22.0, to: 7.25 });
+    }
+
+    #[tokio::test]
+    async fn unsupported_only_allows_reading_and_turning_off() {
+        let mut backend = MemoryBackend { supported: false, ..backend() };
+        assert!(execute(&mut backend, &NightLightCommand::On).await.is_err());
+        let command = NightLightCommand::Temperature(NightLightTemperatureArgs { kelvin: 3000 });
+        assert!(execute(&mut backend, &command).await.is_err());
+
+        let output = execute(&mut backend, &NightLightCommand::Status).await.unwrap();
+        assert!(output.ends_with("Active: no, displays do not support gamma adjustments\n"));
+        execute(&mut backend, &NightLightCommand::Off).await.unwrap();
+        assert!(!backend.settings.enabled);
+    }
+
+    #[test]
+    fn hours_are_formatted_as_time_of_day() {
+        assert_eq!(format_hours(0.0), "00:00");
+        assert_eq!(format_hours(20.5), "20:30");
+        assert_eq!(format_hours(23.999), "00:00");
+    }
+}
@@ -0,0 +1,775 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, Context};
+use futures_util::StreamExt;
+
+use crate::{
+    arrange::fix_layout,
+    cli::{MaxResolution, MonitorSelector, Scaling, SetArgs},
+    display_config::{
+        apply_monitors_config,
+        get_current_state::{self, LayoutMode, LogicalMonitorTransform, MonitorColorMode, RefreshRateMode},
+        DisplayConfigProxy, MonitorsChangedStream,
+    },
+    history,
+    validate::validate_layout,
+};
+
+/// How many times config is re-planned and re-applied if mutter reports that it was based on
+/// a stale serial, e.g. because a monitor was hotplugged in between.
+const MAX_APPLY_ATTEMPTS: usize = 3;
+
+/// Allowed difference between requested and selected scale, when scale is requested as an
+/// exact value.
+pub const DEFAULT_SCALE_TOLERANCE: f64 = 0.01;
+
+/// Monitors often report rates slightly lower than advertised, e.g. 59.94Hz instead of 60Hz,
+/// which should still satisfy a minimal refresh rate.
+const MIN_REFRESH_RATE_TOLERANCE: f64 = 0.5;
+
+/// Allowed difference between aspect ratios, needed for resolutions like 1366x768 that are
+/// not exactly 16:9.
+const ASPECT_RATIO_TOLERANCE: f64 = 0.02;
+
+/// Converts current state into a config that could be passed to ApplyMonitorsConfig as is,
+/// so that it can be used as a base for modifications.
+pub fn current_config(current_state: &get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    current_state.logical_monitors.iter().map(|logical_monitor| {
+        let monitors = logical_monitor.monitors.iter().map(|monitor_id| {
+            let monitor = current_state.monitors.iter()
+                .find(|monitor| &monitor.id == monitor_id)
+                .ok_or(anyhow!("could not find a display with \"{}\" connector name", monitor_id.connector))?;
+            let current_mode = monitor.modes.iter()
+                .find(|mode| mode.properties.is_current.unwrap_or(false))
+                .ok_or(anyhow!("could not find current configuration of \"{}\"", monitor_id.connector))?;
+            Ok(apply_monitors_config::Monitor {
+                connector: monitor_id.connector.clone(),
+                mode: current_mode.id.clone(),
+                properties: apply_monitors_config::MonitorProperties {
+                    underscanning: monitor.properties.is_underscanning,
+                    color_mode: monitor.properties.supported_color_modes.as_ref().and(monitor.properties.color_mode),
+                },
+            })
+        }).collect::<anyhow::Result<_>>()?;
+        Ok(apply_monitors_config::LogicalMonitor {
+            x: logical_monitor.x,
+            y: logical_monitor.y,
+            scale: logical_monitor.scale,
+            transform: logical_monitor.transform,
+            primary: logical_monitor.primary,
+            monitors,
+        })
+    }).collect()
+}
+
+/// Layout mode that mutter currently uses, which is also used for configs that don't specify one.
+pub fn current_layout_mode(current_state: &get_current_state::Response) -> LayoutMode {
+    current_state.properties.layout_mode.unwrap_or_default()
+}
+
+/// Displays are matched by vendor, product and serial, so that configuration follows them if
+/// they were plugged into different ports.
+fn same_display(l: &get_current_state::MonitorId, r: &get_current_state::MonitorId) -> bool {
+    l.vendor == r.vendor && l.product == r.product && l.serial == r.serial
+}
+
+/// Finds a connected display for every one of `wanted` displays, keyed by wanted connector.
+/// Display on the same connector is preferred and every connected display is matched at most
+/// once, so that identical displays, e.g. with empty serials, don't end up on the same one.
+pub fn match_displays<'a>(current_state: &'a get_current_state::Response, wanted: &[get_current_state::MonitorId]) -> HashMap<String, &'a get_current_state::Monitor> {
+    let mut matched = HashMap::new();
+    for wanted_id in wanted {
+        // MonitorId is compared by connector only
+        if let Some(monitor) = current_state.monitors.iter().find(|monitor| &monitor.id == wanted_id && same_display(&monitor.id, wanted_id)) {
+            matched.insert(wanted_id.connector.clone(), monitor);
+        }
+    }
+    for wanted_id in wanted {
+        if matched.contains_key(&wanted_id.connector) {
+            continue;
+        }
+        let monitor = current_state.monitors.iter().find(|monitor| {
+            same_display(&monitor.id, wanted_id) && !matched.values().any(|claimed| claimed.id.connector == monitor.id.connector)
+        });
+        if let Some(monitor) = monitor {
+            matched.insert(wanted_id.connector.clone(), monitor);
+        }
+    }
+    matched
+}
+
+/// Calculates size that logical monitor occupies in a layout with `layout_mode`, based on its
+/// first monitor.
+pub fn logical_size(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitor: &apply_monitors_config::LogicalMonitor,
+) -> anyhow::Result<(i32, i32)> {
+    let monitor = logical_monitor.monitors.first().ok_or(anyhow!("logical monitor does not have any displays attached"))?;
+    let mode = current_state.monitors.iter()
+        .find(|m| m.id.connector == monitor.connector)
+        .and_then(|m| m.modes.iter().find(|mode| mode.id == monitor.mode))
+        .ok_or(anyhow!("display \"{}\" does not have a mode with \"{}\" ID", monitor.connector, monitor.mode))?;
+    let (width, height) = match layout_mode {
+        LayoutMode::Logical => ((mode.width as f64 / logical_monitor.scale).round() as i32, (mode.height as f64 / logical_monitor.scale).round() as i32),
+        LayoutMode::Physical => (mode.width, mode.height),
+    };
+    // Odd transforms are rotated by 90 or 270 degrees
+    if logical_monitor.transform as u32 % 2 == 1 {
+        Ok((height, width))
+    } else {
+        Ok((width, height))
+    }
+}
+
+/// Builds a list of logical monitors that should be passed to ApplyMonitorsConfig in order to
+/// apply changes requested by `set` command.
+pub fn plan_set(current_state: &get_current_state::Response, args: &SetArgs) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    let mut logical_monitors = current_config(current_state)?;
+    for connector in selected_connectors(current_state, args)? {
+        plan_monitor(current_state, &mut logical_monitors, &connector, args)?;
+    }
+    fix_layout(current_state, current_layout_mode(current_state), &mut logical_monitors)?;
+    Ok(logical_monitors)
+}
+
+/// Finds connectors of displays that `set` command should change. Disabled displays are only
+/// selected by `--all` and `--monitor` when they are going to be enabled with `--auto`.
+fn selected_connectors(current_state: &get_current_state::Response, args: &SetArgs) -> anyhow::Result<Vec<String>> {
+    if let Some(connector) = &args.connector {
+        return Ok(vec![connector.clone()]);
+    }
+    let connectors: Vec<_> = current_state.monitors.iter()
+        .filter(|monitor| {
+            let builtin = monitor.properties.is_builtin.unwrap_or(false);
+            match args.monitor {
+                Some(MonitorSelector::Builtin) => builtin,
+                Some(MonitorSelector::External) => !builtin,
+                None => args.all,
+            }
+        })
+        .filter(|monitor| args.auto || current_state.logical_monitors.iter().any(|logical_monitor| logical_monitor.monitors.contains(&monitor.id)))
+        .map(|monitor| monitor.id.connector.clone())
+        .collect();
+    if connectors.is_empty() {
+        return Err(anyhow!("there are no enabled displays matching the selection, use --auto to include disabled ones"));
+    }
+    Ok(connectors)
+}
+
+/// Applies changes requested by `set` command to a single display in `logical_monitors`.
+fn plan_monitor(
+    current_state: &get_current_state::Response,
+    logical_monitors: &mut Vec<apply_monitors_config::LogicalMonitor>,
+    connector: &str,
+    args: &SetArgs,
+) -> anyhow::Result<()> {
+    let monitor = current_state.monitors.iter()
+        .find(|monitor| monitor.id.connector == connector)
+        .ok_or(anyhow!("could not find a display with \"{}\" connector name", connector))?;
+    let logical_monitor = current_state.logical_monitors.iter()
+        .find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id));
+    if logical_monitor.is_none() && !args.auto {
+        return Err(anyhow!("display \"{}\" is disabled, use --auto to enable it", connector));
+    }
+
+    let mut available_modes = monitor.modes.clone();
+    available_modes.sort();
+    available_modes.reverse();
+    let preferred_mode = available_modes.iter()
+        .find(|mode| mode.properties.is_preferred.is_some_and(|f| f));
+    // Disabled display is enabled with its preferred mode
+    let base_mode = match logical_monitor {
+        Some(_) => available_modes.iter()
+            .find(|mode| mode.properties.is_current.is_some_and(|f| f))
+            .ok_or(anyhow!("could not find current configuration of \"{}\"", connector))?,
+        None => preferred_mode
+            .or(available_modes.first())
+            .ok_or(anyhow!("no modes available for \"{}\"", connector))?,
+    };
+
+    let matching_mode = match (&args.mode, args.preferred) {
+        (Some(mode_id), _) => available_modes.iter()
+            .find(|mode| &mode.id == mode_id)
+            .ok_or(anyhow!("display \"{}\" does not have a mode with \"{}\" ID", connector, mode_id))?,
+        (_, true) => {
+            let preferred_mode = preferred_mode.ok_or(anyhow!("display \"{}\" does not have a preferred mode", connector))?;
+            let preferred = (preferred_mode.width as u32, preferred_mode.height as u32, preferred_mode.refresh_rate);
+            vrr_counterpart(&available_modes, preferred, base_mode, connector, args.vrr)?
+        },
+        _ => select_mode(&available_modes, base_mode, connector, args)?,
+    };
+
+    let is_variable = matching_mode.properties.refresh_rate_mode.is_some_and(|mode| mode == RefreshRateMode::Variable);
+    if let (true, Some(min_refresh_rate)) = (is_variable, monitor.properties.min_refresh_rate) {
+        if min_refresh_rate as f64 >= matching_mode.refresh_rate {
+            return Err(anyhow!("display \"{}\" cannot use VRR with {} mode, its minimum refresh rate is {}Hz", connector, matching_mode.id, min_refresh_rate));
+        }
+    }
+
+    let mut supported_scales = matching_mode.supported_scales.clone();
+    let wanted_scale = match (args.scale, args.scaling, logical_monitor) {
+        (Some(scale), _, _) => scale,
+        (_, Some(Scaling::Percent(scale_precent)), _) => scale_precent as f64 / 100.0,
+        (_, Some(Scaling::AutoDpi(target_dpi)), _) => {
+            let dpi = physical_dpi(monitor, matching_mode)
+                .ok_or(anyhow!("display \"{}\" does not report its physical size, cannot calculate its DPI", connector))?;
+            dpi / target_dpi
+        },
+        (None, None, Some(logical_monitor)) if !args.preferred => logical_monitor.scale,
+        _ => matching_mode.preferred_scale,
+    };
+    supported_scales.sort_by(|l, r| (l - wanted_scale).abs().total_cmp(&(r - wanted_scale).abs()));
+    let scale = supported_scales.first().ok_or(anyhow!("display \"{}\" does not have any supported scales", connector))?;
+    let scale_matches = match (args.scale_tolerance, args.scale, args.scaling) {
+        (Some(tolerance), _, _) => (wanted_scale - scale).abs() <= tolerance,
+        (None, Some(_), _) => (wanted_scale - scale).abs() <= DEFAULT_SCALE_TOLERANCE,
+        // Calculated scale is never exact, so closest one is always good enough
+        (None, None, Some(Scaling::AutoDpi(_))) => true,
+        (None, None, _) => (wanted_scale * 4.0).round() == (scale * 4.0).round(),
+    };
+    if !scale_matches {
+        return Err(anyhow!("display \"{}\" does not have any scale close to {:.0}%, closest one is {:.0}%", connector, wanted_scale * 100.0, scale * 100.0));
+    }
+
+    let hdr_supported = monitor.properties.supported_color_modes.as_ref().is_some_and(|modes| modes.contains(&MonitorColorMode::BT2100));
+    let color_mode = args.hdr.map(|hdr| if hdr {MonitorColorMode::BT2100} else {MonitorColorMode::Default})
+        .unwrap_or(monitor.properties.color_mode.unwrap_or(MonitorColorMode::Default));
+    let color_mode = match(color_mode, hdr_supported) {
+        (MonitorColorMode::BT2100, false) => return Err(anyhow!("display \"{}\" does not support HDR", connector)),
+        (MonitorColorMode::Default, false) => None,
+        (mode, true) => Some(mode),
+    };
+
+    let new_monitor = apply_monitors_config::Monitor {
+        connector: monitor.id.connector.clone(),
+        mode: matching_mode.id.clone(),
+        properties: apply_monitors_config::MonitorProperties {
+            underscanning: monitor.properties.is_underscanning,
+            color_mode,
+        }
+    };
+    let existing = logical_monitors.iter_mut()
+        .find(|logical_monitor| logical_monitor.monitors.iter().any(|m| m.connector == new_monitor.connector));
+    match existing {
+        Some(logical_monitor) => {
+            logical_monitor.scale = *scale;
+            for m in logical_monitor.monitors.iter_mut().filter(|m| m.connector == new_monitor.connector) {
+                *m = new_monitor.clone();
+            }
+        },
+        None => {
+            // Newly enabled display is placed to the right of all other displays
+            let mut x = 0;
+            for logical_monitor in logical_monitors.iter() {
+                let (width, _) = logical_size(current_state, current_layout_mode(current_state), logical_monitor)?;
+                x = x.max(logical_monitor.x + width);
+            }
+            logical_monitors.push(apply_monitors_config::LogicalMonitor {
+                x,
+                y: 0,
+                scale: *scale,
+                transform: LogicalMonitorTransform::Normal,
+                primary: logical_monitors.is_empty(),
+                monitors: vec![new_monitor],
+            });
+        },
+    }
+
+    Ok(())
+}
+
+/// Selects a mode based on requested resolution, refresh rate and VRR, falling back to values
+/// of `current_mode` for anything that was not requested. Expects `available_modes` to be sorted
+/// in descending order.
+fn select_mode<'a>(available_modes: &'a [get_current_state::Mode], current_mode: &get_current_state::Mode, connector: &str, args: &SetArgs) -> anyhow::Result<&'a get_current_state::Mode> {
+    let available_modes: Vec<_> = available_modes.iter()
+        .filter(|mode| args.min_refresh_rate.is_none_or(|min_refresh_rate| mode.refresh_rate + MIN_REFRESH_RATE_TOLERANCE >= min_refresh_rate))
+        .collect();
+    if available_modes.is_empty() {
+        return Err(anyhow!("no modes available for \"{}\" with refresh rate of at least {}", connector, args.min_refresh_rate.unwrap_or_default()));
+    }
+
+    let (width, height) = match (args.max_resolution, args.resolution) {
+        (Some(max_resolution), _) => {
+            let mut candidates = available_modes.iter()
+                .filter(|mode| args.aspect.is_none_or(|aspect| has_aspect_ratio(mode, aspect)));
+            let max_mode = match max_resolution {
+                // Modes are sorted by width, so first one is the widest
+                MaxResolution::Width => candidates.next(),
+                MaxResolution::Pixels => candidates.max_by_key(|mode| (mode.width as i64 * mode.height as i64, mode.width)),
+            };
+            max_mode.map(|mode| (mode.width as u32, mode.height as u32))
+                .ok_or(match args.aspect {
+                    Some((aspect_width, aspect_height)) => anyhow!("no modes with {}:{} aspect ratio available for \"{}\"", aspect_width, aspect_height, connector),
+                    None => anyhow!("no modes available for \"{}\"", connector),
+                })?
+        },
+        (_, Some(res)) => res,
+        _ => (current_mode.width as u32, current_mode.height as u32),
+    };
+
+    let mut available_refresh_rates: Vec<_> = available_modes.iter().filter_map(|mode| {
+        if mode.width as u32 == width && mode.height as u32 == height {
+            Some(mode.refresh_rate)
+        } else {
+            None
+        }
+    }).collect();
+    let refresh_rate_cmp = |l: &f64, r: &f64, target: f64| {
+        let l = (l - target).abs() * 100.0;
+        let r = (r - target).abs() * 100.0;
+        (l as u32).cmp(&(r as u32))
+    };
+    let refresh_rate = match (args.max_refresh_rate, args.refresh_rate) {
+        // Modes are only sorted by rounded refresh rate, so 59.94Hz could come before 60Hz
+        (true, _) => available_refresh_rates.iter().max_by(|l, r| l.total_cmp(r)).ok_or(anyhow!("could not find any refresh rate for {}x{} resolution", width, height))?,
+        (_, Some(refresh_rate)) => {
+            available_refresh_rates.sort_by(|l, r| refresh_rate_cmp(l, r, refresh_rate));
+            available_refresh_rates.first().ok_or(anyhow!("could not find refresh rate for {}x{} resolution that is close to {}", width, height, refresh_rate))?
+        },
+        _ => {
+            available_refresh_rates.sort_by(|l, r| refresh_rate_cmp(l, r, current_mode.refresh_rate));
+            available_refresh_rates.first().ok_or(anyhow!("could not find refresh rate for {}x{} resolution that is close to current one", width, height))?
+        }
+    };
+
+    vrr_counterpart(available_modes, (width, height, *refresh_rate), current_mode, connector, args.vrr)
+}
+
+/// Same mode could be available with both fixed and variable refresh rate. Finds the one with
+/// refresh rate mode requested by `vrr`, or keeps whatever `current_mode` uses, if possible.
+fn vrr_counterpart<'a>(
+    available_modes: impl IntoIterator<Item = &'a get_current_state::Mode>,
+    (width, height, refresh_rate): (u32, u32, f64),
+    current_mode: &get_current_state::Mode,
+    connector: &str,
+    vrr: Option<bool>,
+) -> anyhow::Result<&'a get_current_state::Mode> {
+    let refresh_rate_mode = match vrr {
+        Some(true) => RefreshRateMode::Variable,
+        Some(false) => RefreshRateMode::Fixed,
+        None => current_mode.properties.refresh_rate_mode.unwrap_or_default(),
+    };
+    let counterparts: Vec<_> = available_modes.into_iter()
+        .filter(|mode| mode.width as u32 == width && mode.height as u32 == height && mode.refresh_rate == refresh_rate)
+        .collect();
+    let matching_mode = counterparts.iter()
+        .find(|mode| mode.properties.refresh_rate_mode.unwrap_or_default() == refresh_rate_mode);
+    match (matching_mode, vrr) {
+        (Some(mode), _) => Ok(*mode),
+        (None, None) => counterparts.first()
+            .copied()
+            .ok_or(anyhow!("could not find a mode for {}x{}@{:.3}", width, height, refresh_rate)),
+        (None, Some(_)) => Err(anyhow!(
+            "display \"{}\" does not have a {} refresh rate counterpart of {}x{}@{:.3}",
+            connector,
+            if refresh_rate_mode == RefreshRateMode::Variable { "variable" } else { "fixed" },
+            width, height, refresh_rate,
+        )),
+    }
+}
+
+/// Calculates physical pixel density of a display in pixels per inch when using `mode`. Returns
+/// None if display does not report its physical size, which is common for projectors and TVs.
+pub fn physical_dpi(monitor: &get_current_state::Monitor, mode: &get_current_state::Mode) -> Option<f64> {
+    let width_mm = monitor.properties.width_mm.filter(|width_mm| *width_mm > 0)?;
+    let height_mm = monitor.properties.height_mm.filter(|height_mm| *height_mm > 0)?;
+    let diagonal_px = (mode.width as f64).hypot(mode.height as f64);
+    let diagonal_in = (width_mm as f64).hypot(height_mm as f64) / 25.4;
+    Some(diagonal_px / diagonal_in)
+}
+
+fn has_aspect_ratio(mode: &get_current_state::Mode, (aspect_width, aspect_height): (u32, u32)) -> bool {
+    let mode_ratio = mode.width as f64 / mode.height as f64;
+    let aspect_ratio = aspect_width as f64 / aspect_height as f64;
+    (mode_ratio - aspect_ratio).abs() / aspect_ratio <= ASPECT_RATIO_TOLERANCE
+}
+
+/// Mutter refuses to apply configs in some sessions, e.g. when the screen is locked or when
+/// session is controlled by remote desktop. Check it in advance to give a meaningful error.
+pub async fn ensure_apply_allowed(proxy: &DisplayConfigProxy<'_>) -> anyhow::Result<()> {
+    if !proxy.apply_monitors_config_allowed().await? {
+        return Err(anyhow!("mutter does not allow changing monitors configuration in this session, make sure that the screen is unlocked and that it is not controlled by a remote desktop session"));
+    }
+    Ok(())
+}
+
+/// Plans a config with `plan` against `current_state` and applies it. If mutter rejects it
+/// because display configuration changed in the meantime - fetches a fresh state, plans
+/// config again and retries a bounded number of times. Once applied, configuration that was
+/// replaced is recorded in history, so that it could be restored with `undo`. Returns config
+/// that was applied.
+pub async fn apply_with_retry<F>(
+    proxy: &DisplayConfigProxy<'_>,
+    current_state: get_current_state::Response,
+    method: apply_monitors_config::Method,
+    layout_mode: Option<LayoutMode>,
+    plan: F,
+) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>>
+where
+    F: Fn(&get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>>,
+{
+    let (replaced_state, logical_monitors) = apply_without_history(proxy, current_state, method, layout_mode, plan).await?;
+    if method != apply_monitors_config::Method::Verify {
+        // Config is already applied, so failing to record it should not fail the whole command
+        if let Err(err) = history::record(&replaced_state) {
+            eprintln!("warning: could not record previous display configuration: {err:#}");
+        }
+    }
+    Ok(logical_monitors)
+}
+
+/// Same as `apply_with_retry`, but does not record anything in history. Returns state that
+/// config was applied over, along with the config.
+pub async fn apply_without_history<F>(
+    proxy: &DisplayConfigProxy<'_>,
+    mut current_state: get_current_state::Response,
+    method: apply_monitors_config::Method,
+    layout_mode: Option<LayoutMode>,
+    plan: F,
+) -> anyhow::Result<(get_current_state::Response, Vec<apply_monitors_config::LogicalMonitor>)>
+where
+    F: Fn(&get_current_state::Response) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>>,
+{
+    for attempt in 1..=MAX_APPLY_ATTEMPTS {
+        // Sizes depend on layout mode that config is going to be applied with
+        let effective_layout_mode = layout_mode.unwrap_or_else(|| current_layout_mode(&current_state));
+        let logical_monitors = if attempt == 1 {
+            plan(&current_state)?
+        } else {
+            plan(&current_state).context("display configuration has changed while applying config")?
+        };
+        validate_layout(&current_state, effective_layout_mode, &logical_monitors)?;
+        match proxy.apply_monitors_config(
+            current_state.serial,
+            method,
+            logical_monitors.clone(),
+            apply_monitors_config::Properties{
+                layout_mode,
+                monitors_for_lease: None,
+            },
+        ).await {
+            Ok(()) => return Ok((current_state, logical_monitors)),
+            Err(err) if is_stale_serial_error(&err) && attempt < MAX_APPLY_ATTEMPTS => {
+                current_state = proxy.get_current_state().await?;
+            },
+            Err(err) if is_stale_serial_error(&err) => {
+                return Err(anyhow!("display configuration kept changing while applying config, gave up after {} attempts", MAX_APPLY_ATTEMPTS));
+            },
+            Err(err) => return Err(err.into()),
+        }
+    }
+    unreachable!("apply loop always returns on its last attempt")
+}
+
+/// Mutter responds with AccessDenied when serial that was passed to ApplyMonitorsConfig does
+/// not match its current one.
+fn is_stale_serial_error(err: &zbus::Error) -> bool {
+    let description = match err {
+        zbus::Error::MethodError(_, description, _) => description.as_deref(),
+        zbus::Error::FDO(err) => match err.as_ref() {
+            zbus::fdo::Error::AccessDenied(description) => Some(description.as_str()),
+            _ => None,
+        },
+        _ => None,
+    };
+    description.is_some_and(|description| description.contains("stale"))
+}
+
+/// Waits for MonitorsChanged signals until current state matches `applied` config. Stream
+/// should be created before applying config, so that signal emitted in between is not lost.
+pub async fn wait_for_config(
+    proxy: &DisplayConfigProxy<'_>,
+    mut monitors_changed: MonitorsChangedStream<'_>,
+    applied: &[apply_monitors_config::LogicalMonitor],
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let current_state = proxy.get_current_state().await?;
+        let mismatch = match config_mismatch(&current_state, applied) {
+            Some(mismatch) => mismatch,
+            None => return Ok(()),
+        };
+        match tokio::time::timeout_at(deadline, monitors_changed.next()).await {
+            Ok(Some(_)) => {},
+            Ok(None) => return Err(anyhow!("lost connection to mutter while waiting for config to take effect")),
+            Err(_) => return Err(anyhow!("config did not take effect within {} seconds: {}", timeout.as_secs(), mismatch)),
+        }
+    }
+}
+
+/// Describes first difference between `current_state` and `applied` config, if there is one.
+fn config_mismatch(current_state: &get_current_state::Response, applied: &[apply_monitors_config::LogicalMonitor]) -> Option<String> {
+    for applied_logical_monitor in applied {
+        for applied_monitor in &applied_logical_monitor.monitors {
+            let connector = &applied_monitor.connector;
+            let Some(monitor) = current_state.monitors.iter().find(|monitor| &monitor.id.connector == connector) else {
+                return Some(format!("display \"{}\" is disconnected", connector));
+            };
+            let Some(logical_monitor) = current_state.logical_monitors.iter().find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id)) else {
+                return Some(format!("display \"{}\" is disabled", connector));
+            };
+            let current_mode = monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false));
+            if current_mode.is_none_or(|mode| mode.id != applied_monitor.mode) {
+                return Some(format!("display \"{}\" uses mode {} instead of {}", connector, current_mode.map(|mode| mode.id.as_str()).unwrap_or("none"), applied_monitor.mode));
+            }
+            if (logical_monitor.scale - applied_logical_monitor.scale).abs() > f64::EPSILON {
+                return Some(format!("display \"{}\" uses scale {} instead of {}", connector, logical_monitor.scale, applied_logical_monitor.scale));
+            }
+            if logical_monitor.transform != applied_logical_monitor.transform {
+                return Some(format!("display \"{}\" uses transform {:?} instead of {:?}", connector, logical_monitor.transform, applied_logical_monitor.transform));
+            }
+            let color_mode = monitor.properties.color_mode.unwrap_or_default();
+            if applied_monitor.properties.color_mode.is_some_and(|applied_color_mode| applied_color_mode != color_mode) {
+                return Some(format!("display \"{}\" uses color mode {:?} instead of {:?}", connector, color_mode, applied_monitor.properties.color_mode.unwrap_or_default()));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::{
+        cli::{Cli, Command},
+        fixtures::{logical_monitor, mode, monitor, monitor_with_modes, state, vrr_mode},
+    };
+
+    fn plan(current_state: &get_current_state::Response, args: &str) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+        let cli = Cli::try_parse_from(format!("displayconfig-mutter set {args}").split_whitespace())?;
+        let Command::Set(args) = cli.command else {
+            unreachable!();
+        };
+        plan_set(current_state, &args)
+    }
+
+    /// Modes of planned displays, e.g. "DP-1 1920x1080@60.000"
+    fn modes(logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Vec<String> {
+        logical_monitors.iter()
+            .flat_map(|logical_monitor| &logical_monitor.monitors)
+            .map(|monitor| format!("{} {}", monitor.connector, monitor.mode))
+            .collect()
+    }
+
+    /// Laptop with a TV that uses 1920x1080@60, while also supporting a DCI 4K mode that is
+    /// wider than 4K UHD, an ultrawide mode and a VRR mode
+    fn laptop_with_tv() -> get_current_state::Response {
+        let tv = monitor_with_modes("HDMI-1", vec![
+            mode(1920, 1080, 60.0),
+            mode(4096, 2160, 60.0),
+            mode(3840, 2160, 60.0),
+            mode(3840, 2160, 59.94),
+            mode(3840, 2160, 30.0),
+            mode(2560, 1080, 144.0),
+            mode(1920, 1080, 59.94),
+            mode(1920, 1080, 144.0),
+            vrr_mode(1920, 1080, 144.0),
+            mode(1366, 768, 60.0),
+        ]);
+        state(vec![monitor("eDP-1", &[(1920, 1200)]), tv], vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.0, &["HDMI-1"])])
+    }
+
+    #[test]
+    fn mode_is_selected_by_exact_id() {
+        let current_state = laptop_with_tv();
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --mode 3840x2160@59.940").unwrap()), ["eDP-1 1920x1200@60.000", "HDMI-1 3840x2160@59.940"]);
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --mode 1920x1080@144.000+vrr").unwrap())[1], "HDMI-1 1920x1080@144.000+vrr");
+        let err = plan(&current_state, "-c HDMI-1 --mode 3840x2160@59.94").unwrap_err();
+        assert_eq!(err.to_string(), "display \"HDMI-1\" does not have a mode with \"3840x2160@59.94\" ID");
+        assert!(plan(&current_state, "-c HDMI-1 --mode 3840x2160@59.940 --resolution 1920x1080").is_err());
+    }
+
+    #[test]
+    fn max_resolution_by_pixels_or_width() {
+        let current_state = laptop_with_tv();
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --max-resolution").unwrap())[1], "HDMI-1 4096x2160@60.000");
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --max-resolution=width").unwrap())[1], "HDMI-1 4096x2160@60.000");
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --max-resolution --aspect 16:9").unwrap())[1], "HDMI-1 3840x2160@60.000");
+
+        // Ultrawide mode is the widest one, but has less pixels than 2048x1536
+        let display = monitor("DP-1", &[(1920, 1080), (2560, 1080), (2048, 1536)]);
+        let current_state = state(vec![display], vec![logical_monitor(0, 0, 1.0, &["DP-1"])]);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --max-resolution=pixels").unwrap()), ["DP-1 2048x1536@60.000"]);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --max-resolution=width").unwrap()), ["DP-1 2560x1080@60.000"]);
+    }
+
+    #[test]
+    fn aspect_ratio_allows_small_difference() {
+        let current_state = state(vec![monitor("DP-1", &[(1280, 1024), (1366, 768)])], vec![logical_monitor(0, 0, 1.0, &["DP-1"])]);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --max-resolution").unwrap()), ["DP-1 1280x1024@60.000"]);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --max-resolution --aspect 16:9").unwrap()), ["DP-1 1366x768@60.000"]);
+        let err = plan(&current_state, "-c DP-1 --max-resolution --aspect 4:3").unwrap_err();
+        assert_eq!(err.to_string(), "no modes with 4:3 aspect ratio available for \"DP-1\"");
+        assert!(plan(&current_state, "-c DP-1 --aspect 16:9").is_err());
+    }
+
+    #[test]
+    fn min_refresh_rate_allows_half_hertz_less() {
+        let current_state = laptop_with_tv();
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --max-resolution --min-refresh-rate 100").unwrap())[1], "HDMI-1 2560x1080@144.000");
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --refresh-rate 59.94 --min-refresh-rate 60.4").unwrap())[1], "HDMI-1 1920x1080@59.940");
+        assert_eq!(modes(&plan(&current_state, "-c HDMI-1 --refresh-rate 59.94 --min-refresh-rate 60.5").unwrap())[1], "HDMI-1 1920x1080@60.000");
+        let err = plan(&current_state, "-c HDMI-1 --min-refresh-rate 200").unwrap_err();
+        assert_eq!(err.to_string(), "no modes available for \"HDMI-1\" with refresh rate of at least 200");
+    }
+
+    #[test]
+    fn exact_scale_has_to_be_within_tolerance() {
+        let mut laptop = monitor("eDP-1", &[(2560, 1600)]);
+        laptop.modes[0].supported_scales = vec![1.0, 1.3333333730697632, 1.6666666269302368, 2.0];
+        let current_state = state(vec![laptop], vec![logical_monitor(0, 0, 1.0, &["eDP-1"])]);
+        let scale = |args: &str| plan(&current_state, args).map(|logical_monitors| logical_monitors[0].scale);
+
+        assert_eq!(scale("-c eDP-1 --scale 1.3333").unwrap(), 1.3333333730697632);
+        assert_eq!(scale("-c eDP-1 --scale 1.67").unwrap(), 1.6666666269302368);
+        let err = scale("-c eDP-1 --scale 1.5").unwrap_err();
+        assert_eq!(err.to_string(), "display \"eDP-1\" does not have any scale close to 150%, closest one is 133%");
+        assert_eq!(scale("-c eDP-1 --scale 1.5 --scale-tolerance 0.2").unwrap(), 1.3333333730697632);
+        assert!(scale("-c eDP-1 --scaling 150 --scale-tolerance 0.1").is_err());
+        assert!(scale("-c eDP-1 --scale-tolerance 0.1").is_err());
+
+        // Percentage is rounded to a closest 25% step instead
+        assert_eq!(scale("-c eDP-1 --scaling 125").unwrap(), 1.3333333730697632);
+        let err = scale("-c eDP-1 --scaling 150").unwrap_err();
+        assert_eq!(err.to_string(), "display \"eDP-1\" does not have any scale close to 150%, closest one is 133%");
+    }
+
+    #[test]
+    fn auto_dpi_uses_physical_size() {
+        // 27" 4K display
+        let mut display = monitor("DP-1", &[(3840, 2160), (1920, 1080)]);
+        display.properties.width_mm = Some(597);
+        display.properties.height_mm = Some(336);
+        assert_eq!(physical_dpi(&display, &display.modes[0]).unwrap().round(), 163.0);
+        assert_eq!(physical_dpi(&display, &display.modes[1]).unwrap().round(), 82.0);
+
+        let current_state = state(vec![display.clone()], vec![logical_monitor(0, 0, 1.0, &["DP-1"])]);
+        let scale = |args: &str| plan(&current_state, args).map(|logical_monitors| logical_monitors[0].scale);
+        assert_eq!(scale("-c DP-1 --scaling auto-dpi").unwrap(), 1.75);
+        assert_eq!(scale("-c DP-1 --scaling auto-dpi=144").unwrap(), 1.25);
+        assert_eq!(scale("-c DP-1 --resolution 1920x1080 --scaling auto-dpi").unwrap(), 1.0);
+        assert!(scale("-c DP-1 --scaling auto-dpi=0").is_err());
+
+        // Projectors and TVs often report zero size
+        display.properties.width_mm = Some(0);
+        assert!(physical_dpi(&display, &display.modes[0]).is_none());
+        let current_state = state(vec![display], vec![logical_monitor(0, 0, 1.0, &["DP-1"])]);
+        let err = plan(&current_state, "-c DP-1 --scaling auto-dpi").unwrap_err();
+        assert_eq!(err.to_string(), "display \"DP-1\" does not report its physical size, cannot calculate its DPI");
+    }
+
+    #[test]
+    fn vrr_switches_to_counterpart_of_selected_mode() {
+        let current_state = laptop_with_tv();
+        let tv_mode = |args: &str| plan(&current_state, args).map(|logical_monitors| modes(&logical_monitors)[1].clone());
+        assert_eq!(tv_mode("-c HDMI-1 --refresh-rate 144").unwrap(), "HDMI-1 1920x1080@144.000");
+        assert_eq!(tv_mode("-c HDMI-1 --refresh-rate 144 --vrr true").unwrap(), "HDMI-1 1920x1080@144.000+vrr");
+        let err = tv_mode("-c HDMI-1 --vrr true").unwrap_err();
+        assert_eq!(err.to_string(), "display \"HDMI-1\" does not have a variable refresh rate counterpart of 1920x1080@60.000");
+        assert_eq!(tv_mode("-c HDMI-1 --vrr false").unwrap(), "HDMI-1 1920x1080@60.000");
+    }
+
+    #[test]
+    fn vrr_is_kept_unless_requested_otherwise() {
+        let mut current_state = laptop_with_tv();
+        for mode in &mut current_state.monitors[1].modes {
+            mode.properties.is_current = Some(mode.id == "1920x1080@144.000+vrr");
+        }
+        let tv_mode = |current_state: &get_current_state::Response, args: &str| plan(current_state, args).map(|logical_monitors| modes(&logical_monitors)[1].clone());
+        assert_eq!(tv_mode(&current_state, "-c HDMI-1 --scale 2").unwrap(), "HDMI-1 1920x1080@144.000+vrr");
+        assert_eq!(tv_mode(&current_state, "-c HDMI-1 --vrr false").unwrap(), "HDMI-1 1920x1080@144.000");
+        // Falls back to fixed refresh rate when selected mode does not have a VRR counterpart
+        assert_eq!(tv_mode(&current_state, "-c HDMI-1 --resolution 2560x1080").unwrap(), "HDMI-1 2560x1080@144.000");
+
+        current_state.monitors[1].properties.min_refresh_rate = Some(150);
+        let err = tv_mode(&current_state, "-c HDMI-1 --scale 2").unwrap_err();
+        assert_eq!(err.to_string(), "display \"HDMI-1\" cannot use VRR with 1920x1080@144.000+vrr mode, its minimum refresh rate is 150Hz");
+    }
+
+    /// Laptop with an enabled external display and a disabled TV
+    fn laptop_with_disabled_tv() -> get_current_state::Response {
+        state(
+            vec![
+                monitor("eDP-1", &[(1920, 1200)]),
+                monitor_with_modes("DP-1", vec![mode(2560, 1440, 60.0), mode(2560, 1440, 144.0)]),
+                monitor("HDMI-1", &[(1920, 1080)]),
+            ],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 1.0, &["DP-1"])],
+        )
+    }
+
+    /// Positions and scales of planned logical monitors, e.g. "DP-1 at 1920,0 x1"
+    fn positions(logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Vec<String> {
+        logical_monitors.iter()
+            .map(|logical_monitor| format!("{} at {},{} x{}", logical_monitor.monitors[0].connector, logical_monitor.x, logical_monitor.y, logical_monitor.scale))
+            .collect()
+    }
+
+    #[test]
+    fn all_changes_every_enabled_display_independently() {
+        let current_state = laptop_with_disabled_tv();
+        let logical_monitors = plan(&current_state, "--all --max-refresh-rate").unwrap();
+        assert_eq!(modes(&logical_monitors), ["eDP-1 1920x1200@60.000", "DP-1 2560x1440@144.000"]);
+
+        let logical_monitors = plan(&current_state, "--all --auto --scale 2").unwrap();
+        assert_eq!(modes(&logical_monitors), ["eDP-1 1920x1200@60.000", "DP-1 2560x1440@60.000", "HDMI-1 1920x1080@60.000"]);
+        assert_eq!(positions(&logical_monitors), ["eDP-1 at 0,0 x2", "DP-1 at 960,0 x2", "HDMI-1 at 2240,0 x2"]);
+
+        assert!(plan(&current_state, "--all -c DP-1 --scale 2").is_err());
+    }
+
+    #[test]
+    fn monitor_selects_builtin_or_external_displays() {
+        let current_state = laptop_with_disabled_tv();
+        let logical_monitors = plan(&current_state, "--monitor builtin --scale 2").unwrap();
+        assert_eq!(positions(&logical_monitors), ["eDP-1 at 0,0 x2", "DP-1 at 960,0 x1"]);
+
+        let logical_monitors = plan(&current_state, "--monitor external --max-refresh-rate").unwrap();
+        assert_eq!(modes(&logical_monitors), ["eDP-1 1920x1200@60.000", "DP-1 2560x1440@144.000"]);
+        let logical_monitors = plan(&current_state, "--monitor external --auto").unwrap();
+        assert_eq!(positions(&logical_monitors), ["eDP-1 at 0,0 x1", "DP-1 at 1920,0 x1", "HDMI-1 at 4480,0 x1"]);
+    }
+
+    #[test]
+    fn disabled_displays_require_auto() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("HDMI-1", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"])],
+        );
+        let err = plan(&current_state, "--monitor external --scale 2").unwrap_err();
+        assert_eq!(err.to_string(), "there are no enabled displays matching the selection, use --auto to include disabled ones");
+        let err = plan(&current_state, "-c HDMI-1 --scale 2").unwrap_err();
+        assert_eq!(err.to_string(), "display \"HDMI-1\" is disabled, use --auto to enable it");
+        let logical_monitors = plan(&current_state, "-c HDMI-1 --auto").unwrap();
+        assert_eq!(positions(&logical_monitors), ["eDP-1 at 0,0 x1", "HDMI-1 at 1920,0 x1"]);
+    }
+
+    /// Display that currently uses 1920x1080@144 with VRR, while preferring 2560x1440@60
+    fn gaming_display(preferred_has_vrr: bool) -> get_current_state::Response {
+        let mut modes = vec![vrr_mode(1920, 1080, 144.0), mode(1920, 1080, 144.0), mode(2560, 1440, 60.0)];
+        if preferred_has_vrr {
+            modes.push(vrr_mode(2560, 1440, 60.0));
+        }
+        let mut display = monitor_with_modes("DP-1", modes);
+        display.modes[0].properties.is_preferred = Some(false);
+        display.modes[2].properties.is_preferred = Some(true);
+        state(vec![display], vec![logical_monitor(0, 0, 1.0, &["DP-1"])])
+    }
+
+    #[test]
+    fn preferred_mode_keeps_or_switches_vrr() {
+        let current_state = gaming_display(true);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --preferred").unwrap()), ["DP-1 2560x1440@60.000+vrr"]);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --preferred --vrr false").unwrap()), ["DP-1 2560x1440@60.000"]);
+
+        let current_state = gaming_display(false);
+        assert_eq!(modes(&plan(&current_state, "-c DP-1 --preferred").unwrap()), ["DP-1 2560x1440@60.000"]);
+        let err = plan(&current_state, "-c DP-1 --preferred --vrr true").unwrap_err();
+        assert_eq!(err.to_string(), "display \"DP-1\" does not have a variable refresh rate counterpart of 2560x1440@60.000");
+    }
+}
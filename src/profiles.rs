@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+use crate::{
+    dirs,
+    display_config::get_current_state,
+    monitors_xml::{self, Configuration, MonitorsXml},
+};
+
+/// Profiles are files in monitors.xml format, stored in
+/// ~/.config/displayconfig-mutter/profiles/<name>.xml
+pub fn path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty() || name.contains('/') {
+        return Err(anyhow!("invalid profile name \"{name}\""));
+    }
+    Ok(dirs::config_dir()?.join("displayconfig-mutter").join("profiles").join(format!("{name}.xml")))
+}
+
+pub fn read(name: &str) -> anyhow::Result<MonitorsXml> {
+    let path = path(name)?;
+    if !path.exists() {
+        return Err(anyhow!("profile \"{name}\" does not exist, expected it at {}", path.display()));
+    }
+    monitors_xml::read(&path)
+}
+
+/// Selects configuration from a profile for currently connected displays. Profile with a single
+/// configuration is used even if it doesn't exactly match, e.g. when laptop lid is closed.
+pub fn configuration<'a>(name: &str, profile: &'a MonitorsXml, current_state: &get_current_state::Response) -> anyhow::Result<&'a Configuration> {
+    match (profile.matching_configuration(current_state), profile.configurations.as_slice()) {
+        (Some((_, configuration)), _) => Ok(configuration),
+        (None, [configuration]) => Ok(configuration),
+        (None, _) => Err(anyhow!("profile \"{name}\" does not have a configuration for currently connected displays")),
+    }
+}
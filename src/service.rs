@@ -0,0 +1,148 @@
+use serde::Serialize;
+use zbus::{fdo, SignalContext};
+
+use crate::{
+    builtin::with_rotation_check,
+    cli::{MonitorSelector, SetArgs},
+    display_config::{apply_monitors_config, get_current_state::{self, MonitorColorMode, RefreshRateMode}, DisplayConfigProxy},
+    planner::{apply_with_retry, ensure_apply_allowed, plan_set},
+    profiles,
+};
+
+/// Well-known name that is requested on session bus by `serve` command
+pub const SERVICE_NAME: &str = "com.github.displayconfig_mutter";
+/// Path at which service interface is exported
+pub const OBJECT_PATH: &str = "/com/github/displayconfig_mutter";
+
+/// Higher level interface on top of mutter's DisplayConfig, for use by other session tools
+pub struct Service {
+    proxy: DisplayConfigProxy<'static>,
+}
+
+#[derive(Serialize)]
+struct MonitorSummary {
+    connector: String,
+    vendor: String,
+    product: String,
+    serial: String,
+    display_name: Option<String>,
+    builtin: bool,
+    enabled: bool,
+    primary: bool,
+    x: Option<i32>,
+    y: Option<i32>,
+    scale: Option<f64>,
+    mode: Option<ModeSummary>,
+    hdr: bool,
+}
+
+#[derive(Serialize)]
+struct ModeSummary {
+    id: String,
+    width: i32,
+    height: i32,
+    refresh_rate: f64,
+    vrr: bool,
+}
+
+impl MonitorSummary {
+    fn new(current_state: &get_current_state::Response, monitor: &get_current_state::Monitor) -> Self {
+        let logical_monitor = current_state.logical_monitors.iter()
+            .find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id));
+        let mode = logical_monitor
+            .and_then(|_| monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false)))
+            .map(|mode| ModeSummary {
+                id: mode.id.clone(),
+                width: mode.width,
+                height: mode.height,
+                refresh_rate: mode.refresh_rate,
+                vrr: mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable),
+            });
+        Self {
+            connector: monitor.id.connector.clone(),
+            vendor: monitor.id.vendor.clone(),
+            product: monitor.id.product.clone(),
+            serial: monitor.id.serial.clone(),
+            display_name: monitor.properties.display_name.clone(),
+            builtin: monitor.properties.is_builtin.unwrap_or(false),
+            enabled: logical_monitor.is_some(),
+            primary: logical_monitor.is_some_and(|logical_monitor| logical_monitor.primary),
+            x: logical_monitor.map(|logical_monitor| logical_monitor.x),
+            y: logical_monitor.map(|logical_monitor| logical_monitor.y),
+            scale: logical_monitor.map(|logical_monitor| logical_monitor.scale),
+            mode,
+            hdr: monitor.properties.color_mode.is_some_and(|color_mode| color_mode == MonitorColorMode::BT2100),
+        }
+    }
+}
+
+fn to_fdo_error(err: anyhow::Error) -> fdo::Error {
+    fdo::Error::Failed(format!("{err:#}"))
+}
+
+#[zbus::interface(name = "com.github.displayconfig_mutter")]
+impl Service {
+    /// Applies a profile stored in ~/.config/displayconfig-mutter/profiles/<name>.xml
+    async fn apply_profile(&self, name: String, #[zbus(signal_context)] ctxt: SignalContext<'_>) -> fdo::Result<()> {
+        let profile = profiles::read(&name).map_err(to_fdo_error)?;
+        ensure_apply_allowed(&self.proxy).await.map_err(to_fdo_error)?;
+        let current_state = self.proxy.get_current_state().await?;
+        let configuration = profiles::configuration(&name, &profile, &current_state).map_err(to_fdo_error)?;
+        let layout_mode = configuration.layout_mode().map_err(to_fdo_error)?;
+        let orientation_managed = self.proxy.panel_orientation_managed().await?;
+        let plan = with_rotation_check(orientation_managed, |current_state| configuration.plan(current_state));
+        apply_with_retry(&self.proxy, current_state, apply_monitors_config::Method::Temporary, layout_mode, plan)
+            .await
+            .map_err(to_fdo_error)?;
+        Self::profile_applied(&ctxt, &name).await?;
+        Ok(())
+    }
+
+    /// Sets resolution and refresh rate of displays matching selector, which is either a
+    /// connector name, "all", "builtin" or "external". Refresh rate of 0 keeps the closest one
+    /// to current
+    async fn set_mode(&self, selector: String, width: u32, height: u32, rate: f64) -> fdo::Result<()> {
+        let (connector, all, monitor) = match selector.as_str() {
+            "all" => (None, true, None),
+            "builtin" => (None, false, Some(MonitorSelector::Builtin)),
+            "external" => (None, false, Some(MonitorSelector::External)),
+            _ => (Some(selector), false, None),
+        };
+        let args = SetArgs {
+            connector,
+            all,
+            monitor,
+            resolution: Some((width, height)),
+            refresh_rate: (rate > 0.0).then_some(rate),
+            ..Default::default()
+        };
+        ensure_apply_allowed(&self.proxy).await.map_err(to_fdo_error)?;
+        let current_state = self.proxy.get_current_state().await?;
+        apply_with_retry(&self.proxy, current_state, apply_monitors_config::Method::Temporary, None, |current_state| plan_set(current_state, &args))
+            .await
+            .map_err(to_fdo_error)?;
+        Ok(())
+    }
+
+    /// Lists connected displays with their current configuration as a JSON array
+    async fn list_monitors(&self) -> fdo::Result<String> {
+        let current_state = self.proxy.get_current_state().await?;
+        let monitors: Vec<_> = current_state.monitors.iter()
+            .map(|monitor| MonitorSummary::new(&current_state, monitor))
+            .collect();
+        serde_json::to_string(&monitors).map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Emitted after profile was successfully applied
+    #[zbus(signal)]
+    async fn profile_applied(ctxt: &SignalContext<'_>, name: &str) -> zbus::Result<()>;
+}
+
+/// Exports service on session bus and handles requests until the process is terminated
+pub async fn serve(conn: &zbus::Connection) -> anyhow::Result<()> {
+    let proxy = DisplayConfigProxy::new(conn).await?;
+    conn.object_server().at(OBJECT_PATH, Service { proxy }).await?;
+    conn.request_name(SERVICE_NAME).await?;
+    std::future::pending::<()>().await;
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use anyhow::Context;
+use zbus::zvariant::{self, serialized, Endian};
+
+use crate::display_config::get_current_state;
+
+/// Saved states are stored exactly as mutter sends them over D-Bus, because property
+/// dictionaries don't have a lossless representation in formats like JSON.
+fn context() -> serialized::Context {
+    serialized::Context::new_dbus(Endian::Little, 0)
+}
+
+/// Saves display state, so that it could be used later without access to mutter.
+pub fn write(path: &Path, current_state: &get_current_state::Response) -> anyhow::Result<()> {
+    let data = zvariant::to_bytes(context(), current_state)?;
+    std::fs::write(path, data.bytes()).with_context(|| format!("could not write {}", path.display()))
+}
+
+/// Reads display state that was saved with [`write`].
+pub fn read(path: &Path) -> anyhow::Result<get_current_state::Response> {
+    let bytes = std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let (current_state, _) = serialized::Data::new(bytes, context())
+        .deserialize()
+        .with_context(|| format!("{} is not a saved display state", path.display()))?;
+    Ok(current_state)
+}
@@ -0,0 +1,492 @@
+use anyhow::anyhow;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures_util::StreamExt;
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{
+        canvas::{Canvas, Rectangle},
+        Block, Clear, List, ListItem, ListState, Paragraph,
+    },
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    builtin::rotated_builtin,
+    diagram::{transform_label, CELL_ASPECT_RATIO},
+    display_config::{
+        apply_monitors_config,
+        get_current_state::{self, LogicalMonitorTransform, MonitorColorMode, RefreshRateMode},
+        DisplayConfigProxy,
+    },
+    planner::{apply_with_retry, current_config, current_layout_mode, ensure_apply_allowed, logical_size},
+};
+
+/// How far selected display is moved by arrow keys, in layout coordinates. Holding Shift moves
+/// it by a single pixel instead.
+const MOVE_STEP: i32 = 50;
+
+/// Edges that are closer than this are snapped together after moving a display.
+const SNAP_DISTANCE: i32 = MOVE_STEP / 2;
+
+const TRANSFORMS: [LogicalMonitorTransform; 8] = [
+    LogicalMonitorTransform::Normal,
+    LogicalMonitorTransform::Turned90Deg,
+    LogicalMonitorTransform::Turned180Deg,
+    LogicalMonitorTransform::Turned270Deg,
+    LogicalMonitorTransform::Flipped,
+    LogicalMonitorTransform::Turned90DegFlipped,
+    LogicalMonitorTransform::Turned180DegFlipped,
+    LogicalMonitorTransform::Turned270DegFlipped,
+];
+
+const HELP: &str = "Tab: select  Arrows: move  m: mode  s: scale  r: rotation  h: HDR  v: VRR  p: primary  a: apply  u: revert  q: quit";
+
+enum PickerValue {
+    Mode(String),
+    Scale(f64),
+    Transform(LogicalMonitorTransform),
+}
+
+struct Picker {
+    title: &'static str,
+    options: Vec<(String, PickerValue)>,
+    state: ListState,
+}
+
+struct App<'a> {
+    proxy: &'a DisplayConfigProxy<'a>,
+    current_state: get_current_state::Response,
+    /// Config that was active when TUI was started, restored by revert
+    original: Vec<apply_monitors_config::LogicalMonitor>,
+    /// Config that is being edited
+    layout: Vec<apply_monitors_config::LogicalMonitor>,
+    selected: usize,
+    /// Whether mutter rotates built-in display according to accelerometer
+    orientation_managed: bool,
+    picker: Option<Picker>,
+    status: String,
+    quit: bool,
+}
+
+/// Runs interactive editor of display configuration until user quits it.
+pub async fn run(proxy: &DisplayConfigProxy<'_>) -> anyhow::Result<()> {
+    let current_state = proxy.get_current_state().await?;
+    let layout = current_config(&current_state)?;
+    if layout.is_empty() {
+        return Err(anyhow!("there are no enabled displays"));
+    }
+    let orientation_managed = proxy.panel_orientation_managed().await?;
+    let mut app = App {
+        proxy,
+        current_state,
+        original: layout.clone(),
+        layout,
+        selected: 0,
+        orientation_managed,
+        picker: None,
+        status: "Editing current configuration".to_string(),
+        quit: false,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        let mut events = EventStream::new();
+        let mut monitors_changed = self.proxy.receive_monitors_changed().await?;
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => self.handle_key(key).await?,
+                    Some(Ok(_)) => {},
+                    Some(Err(err)) => return Err(err.into()),
+                    None => return Ok(()),
+                },
+                changed = monitors_changed.next() => {
+                    if changed.is_none() {
+                        return Err(anyhow!("lost connection to mutter"));
+                    }
+                    self.reload().await?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Picks up configuration changes made outside of TUI, including the ones that were applied
+    /// from it.
+    async fn reload(&mut self) -> anyhow::Result<()> {
+        self.current_state = self.proxy.get_current_state().await?;
+        let layout = current_config(&self.current_state)?;
+        if layout.is_empty() {
+            return Err(anyhow!("all displays were disabled"));
+        }
+        self.layout = layout;
+        self.selected = self.selected.min(self.layout.len() - 1);
+        self.picker = None;
+        Ok(())
+    }
+
+    async fn handle_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        if self.picker.is_some() {
+            return self.handle_picker_key(key).await;
+        }
+        let step = if key.modifiers.contains(KeyModifiers::SHIFT) { 1 } else { MOVE_STEP };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => self.selected = (self.selected + 1) % self.layout.len(),
+            KeyCode::BackTab => self.selected = (self.selected + self.layout.len() - 1) % self.layout.len(),
+            KeyCode::Left => self.move_selected(-step, 0).await?,
+            KeyCode::Right => self.move_selected(step, 0).await?,
+            KeyCode::Up => self.move_selected(0, -step).await?,
+            KeyCode::Down => self.move_selected(0, step).await?,
+            KeyCode::Char('m') => self.picker = self.mode_picker(),
+            KeyCode::Char('s') => self.picker = self.scale_picker(),
+            KeyCode::Char('r') => self.picker = Some(self.rotation_picker()),
+            KeyCode::Char('h') => self.toggle_hdr().await?,
+            KeyCode::Char('v') => self.toggle_vrr().await?,
+            KeyCode::Char('p') => {
+                for (index, logical_monitor) in self.layout.iter_mut().enumerate() {
+                    logical_monitor.primary = index == self.selected;
+                }
+                self.verify().await?;
+            },
+            KeyCode::Char('a') => self.apply(self.layout.clone(), "Applied").await?,
+            KeyCode::Char('u') => self.apply(self.original.clone(), "Reverted").await?,
+            _ => {},
+        }
+        Ok(())
+    }
+
+    async fn handle_picker_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        let Some(picker) = &mut self.picker else {
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.picker = None,
+            KeyCode::Up => picker.state.select_previous(),
+            KeyCode::Down => picker.state.select_next(),
+            KeyCode::Enter => {
+                let picker = self.picker.take().expect("picker is checked above");
+                if let Some((_, value)) = picker.state.selected().and_then(|index| picker.options.into_iter().nth(index)) {
+                    match self.pick(value) {
+                        Ok(()) => self.verify().await?,
+                        Err(err) => self.status = format!("{err:#}"),
+                    }
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn selected_monitor(&self) -> anyhow::Result<&get_current_state::Monitor> {
+        let connector = &self.layout[self.selected].monitors.first()
+            .ok_or(anyhow!("logical monitor does not have any displays attached"))?
+            .connector;
+        self.current_state.monitors.iter()
+            .find(|monitor| &monitor.id.connector == connector)
+            .ok_or(anyhow!("could not find a display with \"{}\" connector name", connector))
+    }
+
+    /// Displays of selected logical monitor, more than one when they are mirrored
+    fn selected_monitors(&self) -> anyhow::Result<Vec<&get_current_state::Monitor>> {
+        self.layout[self.selected].monitors.iter().map(|selected| {
+            self.current_state.monitors.iter()
+                .find(|monitor| monitor.id.connector == selected.connector)
+                .ok_or(anyhow!("could not find a display with \"{}\" connector name", selected.connector))
+        }).collect()
+    }
+
+    /// Finds a mode for every display of selected logical monitor, since mirrored displays have
+    /// to use the same resolution. Refresh rate and refresh rate mode of `mode` are preferred.
+    fn mirrored_modes(&self, mode: &get_current_state::Mode) -> anyhow::Result<Vec<&get_current_state::Mode>> {
+        let refresh_rate_mode = mode.properties.refresh_rate_mode.unwrap_or_default();
+        self.selected_monitors()?.into_iter().map(|monitor| {
+            monitor.modes.iter()
+                .filter(|other| other.width == mode.width && other.height == mode.height)
+                .min_by(|l, r| {
+                    let differs = |other: &get_current_state::Mode| other.properties.refresh_rate_mode.unwrap_or_default() != refresh_rate_mode;
+                    differs(l).cmp(&differs(r))
+                        .then((l.refresh_rate - mode.refresh_rate).abs().total_cmp(&(r.refresh_rate - mode.refresh_rate).abs()))
+                })
+                .ok_or(anyhow!("Mirrored display \"{}\" does not support {}x{}", monitor.id.connector, mode.width, mode.height))
+        }).collect()
+    }
+
+    fn selected_mode(&self) -> anyhow::Result<&get_current_state::Mode> {
+        let mode_id = &self.layout[self.selected].monitors[0].mode;
+        self.selected_monitor()?.modes.iter()
+            .find(|mode| &mode.id == mode_id)
+            .ok_or(anyhow!("could not find mode \"{}\"", mode_id))
+    }
+
+    fn mode_picker(&self) -> Option<Picker> {
+        let monitor = self.selected_monitor().ok()?;
+        let current = &self.layout[self.selected].monitors[0].mode;
+        let options: Vec<_> = monitor.modes.iter().map(|mode| {
+            let vrr = mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable);
+            let label = format!("{}x{}@{:.3}{}", mode.width, mode.height, mode.refresh_rate, if vrr { " VRR" } else { "" });
+            (label, PickerValue::Mode(mode.id.clone()))
+        }).collect();
+        let selected = monitor.modes.iter().position(|mode| &mode.id == current);
+        Some(Picker { title: "Mode", options, state: ListState::default().with_selected(selected) })
+    }
+
+    fn scale_picker(&self) -> Option<Picker> {
+        let mode = self.selected_mode().ok()?;
+        let current = self.layout[self.selected].scale;
+        let options: Vec<_> = mode.supported_scales.iter()
+            .map(|scale| (format!("{:.0}% ({scale:.4})", scale * 100.0), PickerValue::Scale(*scale)))
+            .collect();
+        let selected = mode.supported_scales.iter().position(|scale| (scale - current).abs() < f64::EPSILON);
+        Some(Picker { title: "Scale", options, state: ListState::default().with_selected(selected) })
+    }
+
+    fn rotation_picker(&self) -> Picker {
+        let current = self.layout[self.selected].transform;
+        let options = TRANSFORMS.iter()
+            .map(|transform| match transform_label(*transform) {
+                "" => ("normal".to_string(), PickerValue::Transform(*transform)),
+                label => (label.to_string(), PickerValue::Transform(*transform)),
+            })
+            .collect();
+        let selected = TRANSFORMS.iter().position(|transform| *transform == current);
+        Picker { title: "Rotation", options, state: ListState::default().with_selected(selected) }
+    }
+
+    /// Changes selected logical monitor, fails without changing anything if picked value could
+    /// not be used for all of its displays.
+    fn pick(&mut self, value: PickerValue) -> anyhow::Result<()> {
+        match value {
+            PickerValue::Mode(mode_id) => {
+                let mode = self.selected_monitor()?.modes.iter()
+                    .find(|mode| mode.id == mode_id)
+                    .ok_or(anyhow!("could not find mode \"{}\"", mode_id))?;
+                let modes = self.mirrored_modes(mode)?;
+                let current_scale = self.layout[self.selected].scale;
+                let scale_supported = modes.iter()
+                    .all(|mode| mode.supported_scales.iter().any(|scale| (scale - current_scale).abs() < f64::EPSILON));
+                let preferred_scale = mode.preferred_scale;
+                let mode_ids: Vec<_> = modes.into_iter().map(|mode| mode.id.clone()).collect();
+                let logical_monitor = &mut self.layout[self.selected];
+                if !scale_supported {
+                    logical_monitor.scale = preferred_scale;
+                }
+                for (monitor, mode_id) in logical_monitor.monitors.iter_mut().zip(mode_ids) {
+                    monitor.mode = mode_id;
+                }
+            },
+            PickerValue::Scale(scale) => self.layout[self.selected].scale = scale,
+            PickerValue::Transform(transform) => self.layout[self.selected].transform = transform,
+        }
+        Ok(())
+    }
+
+    /// Toggles HDR on all displays of selected logical monitor, so all of them have to support it.
+    async fn toggle_hdr(&mut self) -> anyhow::Result<()> {
+        let unsupported = self.selected_monitors()?.into_iter()
+            .find(|monitor| !monitor.properties.supported_color_modes.as_ref().is_some_and(|modes| modes.contains(&MonitorColorMode::BT2100)));
+        if let Some(monitor) = unsupported {
+            self.status = format!("Display \"{}\" does not support HDR", monitor.id.connector);
+            return Ok(());
+        }
+        let monitors = &mut self.layout[self.selected].monitors;
+        let color_mode = match monitors[0].properties.color_mode {
+            Some(MonitorColorMode::BT2100) => MonitorColorMode::Default,
+            _ => MonitorColorMode::BT2100,
+        };
+        for monitor in monitors.iter_mut() {
+            monitor.properties.color_mode = Some(color_mode);
+        }
+        self.verify().await
+    }
+
+    async fn toggle_vrr(&mut self) -> anyhow::Result<()> {
+        let mode = self.selected_mode()?;
+        let refresh_rate_mode = match mode.properties.refresh_rate_mode.unwrap_or_default() {
+            RefreshRateMode::Variable => RefreshRateMode::Fixed,
+            RefreshRateMode::Fixed => RefreshRateMode::Variable,
+        };
+        // Every mirrored display switches its own mode, all of them have to have a counterpart
+        let mut counterparts = Vec::new();
+        for (monitor, selected) in self.selected_monitors()?.into_iter().zip(&self.layout[self.selected].monitors) {
+            let Some(mode) = monitor.modes.iter().find(|mode| mode.id == selected.mode) else {
+                return Err(anyhow!("could not find mode \"{}\"", selected.mode));
+            };
+            let counterpart = monitor.modes.iter().find(|other| {
+                other.width == mode.width && other.height == mode.height && other.refresh_rate == mode.refresh_rate
+                    && other.properties.refresh_rate_mode.unwrap_or_default() == refresh_rate_mode
+            });
+            match counterpart {
+                Some(counterpart) => counterparts.push(counterpart.id.clone()),
+                None => {
+                    self.status = format!(
+                        "{}x{}@{:.3} of \"{}\" does not have a {} refresh rate counterpart",
+                        mode.width, mode.height, mode.refresh_rate, monitor.id.connector,
+                        if refresh_rate_mode == RefreshRateMode::Variable { "variable" } else { "fixed" },
+                    );
+                    return Ok(());
+                },
+            }
+        }
+        for (monitor, counterpart) in self.layout[self.selected].monitors.iter_mut().zip(counterparts) {
+            monitor.mode = counterpart;
+        }
+        self.verify().await
+    }
+
+    async fn move_selected(&mut self, dx: i32, dy: i32) -> anyhow::Result<()> {
+        let sizes = self.layout.iter()
+            .map(|logical_monitor| logical_size(&self.current_state, current_layout_mode(&self.current_state), logical_monitor))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (width, height) = sizes[self.selected];
+        let others = self.layout.iter().zip(&sizes).enumerate()
+            .filter(|(index, _)| *index != self.selected)
+            .map(|(_, (logical_monitor, size))| (logical_monitor.x, logical_monitor.y, size.0, size.1));
+        let (x_edges, y_edges): (Vec<_>, Vec<_>) = others
+            .map(|(x, y, width, height)| ([x, x + width], [y, y + height]))
+            .unzip();
+
+        let logical_monitor = &mut self.layout[self.selected];
+        if dx != 0 {
+            logical_monitor.x = snap(logical_monitor.x + dx, width, x_edges.concat().as_slice(), dx.abs());
+        }
+        if dy != 0 {
+            logical_monitor.y = snap(logical_monitor.y + dy, height, y_edges.concat().as_slice(), dy.abs());
+        }
+        // Mutter requires layout to start at the origin
+        let min_x = self.layout.iter().map(|logical_monitor| logical_monitor.x).min().unwrap_or_default();
+        let min_y = self.layout.iter().map(|logical_monitor| logical_monitor.y).min().unwrap_or_default();
+        for logical_monitor in &mut self.layout {
+            logical_monitor.x -= min_x;
+            logical_monitor.y -= min_y;
+        }
+        self.verify().await
+    }
+
+    /// Asks mutter to validate edited config without applying it, so that problems are shown
+    /// while editing.
+    async fn verify(&mut self) -> anyhow::Result<()> {
+        let layout = self.layout.clone();
+        let result = apply_with_retry(self.proxy, self.current_state.clone(), apply_monitors_config::Method::Verify, None, |_| Ok(layout.clone())).await;
+        let rotated = rotated_builtin(&self.current_state, &self.layout).filter(|_| self.orientation_managed);
+        self.status = match (result, rotated) {
+            (Ok(_), Some(connector)) => format!("Rotation of \"{connector}\" will be reverted by auto-rotation unless it is locked"),
+            (Ok(_), None) => "Configuration is valid, press \"a\" to apply it".to_string(),
+            (Err(err), _) => format!("Invalid configuration: {err:#}"),
+        };
+        Ok(())
+    }
+
+    async fn apply(&mut self, layout: Vec<apply_monitors_config::LogicalMonitor>, done: &str) -> anyhow::Result<()> {
+        if let Err(err) = ensure_apply_allowed(self.proxy).await {
+            self.status = format!("{err:#}");
+            return Ok(());
+        }
+        let result = apply_with_retry(self.proxy, self.current_state.clone(), apply_monitors_config::Method::Temporary, None, |_| Ok(layout.clone())).await;
+        self.status = match result {
+            Ok(_) => done.to_string(),
+            Err(err) => format!("Could not apply configuration: {err:#}"),
+        };
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status, help] = Layout::vertical([Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)]).areas(frame.area());
+        let [arrangement, details] = Layout::horizontal([Constraint::Min(0), Constraint::Length(36)]).areas(main);
+
+        self.draw_arrangement(frame, arrangement);
+        frame.render_widget(Paragraph::new(self.details()).block(Block::bordered().title("Display")), details);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+        frame.render_widget(Paragraph::new(HELP).style(Style::default().add_modifier(Modifier::DIM)), help);
+
+        if let Some(picker) = &mut self.picker {
+            let height = (picker.options.len() as u16 + 2).min(main.height);
+            let area = Rect { x: details.x, y: details.y, width: details.width, height };
+            let items: Vec<_> = picker.options.iter().map(|(label, _)| ListItem::new(label.as_str())).collect();
+            let list = List::new(items)
+                .block(Block::bordered().title(picker.title))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(list, area, &mut picker.state);
+        }
+    }
+
+    fn draw_arrangement(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered().title("Arrangement");
+        let inner = block.inner(area);
+        let boxes: Vec<_> = self.layout.iter().enumerate().filter_map(|(index, logical_monitor)| {
+            let (width, height) = logical_size(&self.current_state, current_layout_mode(&self.current_state), logical_monitor).ok()?;
+            let connector = logical_monitor.monitors.iter().map(|monitor| monitor.connector.as_str()).collect::<Vec<_>>().join("+");
+            Some((index, logical_monitor.x, logical_monitor.y, width, height, connector, logical_monitor.primary))
+        }).collect();
+        let total_width = boxes.iter().map(|b| b.1 + b.3).max().unwrap_or(1).max(1) as f64;
+        let total_height = boxes.iter().map(|b| b.2 + b.4).max().unwrap_or(1).max(1) as f64;
+        // Keep proportions of displays
+        let unit = (total_width / inner.width.max(1) as f64).max(total_height / (CELL_ASPECT_RATIO * inner.height.max(1) as f64));
+        let x_max = unit * inner.width as f64;
+        let y_max = unit * CELL_ASPECT_RATIO * inner.height as f64;
+
+        let canvas = Canvas::default()
+            .block(block)
+            .x_bounds([0.0, x_max])
+            .y_bounds([0.0, y_max])
+            .paint(|ctx| {
+                for (index, x, y, width, height, connector, primary) in &boxes {
+                    let color = if *index == self.selected { Color::Yellow } else { Color::Gray };
+                    // Canvas origin is in the bottom left corner
+                    let bottom = y_max - (*y + *height) as f64;
+                    ctx.draw(&Rectangle { x: *x as f64, y: bottom, width: *width as f64, height: *height as f64, color });
+                    let label = if *primary { format!("{connector} (primary)") } else { connector.clone() };
+                    let style = Style::default().fg(color);
+                    ctx.print(*x as f64 + unit * 2.0, bottom + *height as f64 / 2.0, Line::styled(label, style));
+                }
+            });
+        frame.render_widget(canvas, area);
+    }
+
+    fn details(&self) -> Vec<Line<'_>> {
+        let logical_monitor = &self.layout[self.selected];
+        let (Ok(monitor), Ok(mode)) = (self.selected_monitor(), self.selected_mode()) else {
+            return Vec::new();
+        };
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        let vrr = mode.properties.refresh_rate_mode.is_some_and(|rate_mode| rate_mode == RefreshRateMode::Variable);
+        let hdr = logical_monitor.monitors[0].properties.color_mode == Some(MonitorColorMode::BT2100);
+        let rotation = match transform_label(logical_monitor.transform) {
+            "" => "normal",
+            label => label,
+        };
+        vec![
+            Line::from(format!("Connector: {}", logical_monitor.monitors.iter().map(|monitor| monitor.connector.as_str()).collect::<Vec<_>>().join("+"))),
+            Line::from(format!("Display: {}", monitor.properties.display_name.as_deref().unwrap_or(&monitor.id.product))),
+            Line::from(format!("Mode: {}x{}@{:.3}", mode.width, mode.height, mode.refresh_rate)),
+            Line::from(format!("Scale: {:.0}%", logical_monitor.scale * 100.0)),
+            Line::from(format!("Rotation: {rotation}")),
+            Line::from(format!("Position: {}, {}", logical_monitor.x, logical_monitor.y)),
+            Line::from(format!("Primary: {}", yes_no(logical_monitor.primary))),
+            Line::from(format!("HDR: {}", yes_no(hdr))),
+            Line::from(format!("VRR: {}", yes_no(vrr))),
+        ]
+    }
+}
+
+/// Aligns either edge of a segment at `start` with length `size` to the closest of `edges`,
+/// if it's within snapping distance. Distance is capped by `step`, so that fine movement is
+/// not undone by snapping.
+fn snap(start: i32, size: i32, edges: &[i32], step: i32) -> i32 {
+    let distance = SNAP_DISTANCE.min(step);
+    edges.iter()
+        .flat_map(|edge| [*edge, *edge - size])
+        .filter(|candidate| (candidate - start).abs() < distance)
+        .min_by_key(|candidate| (candidate - start).abs())
+        .unwrap_or(start)
+}
@@ -0,0 +1,216 @@
+use anyhow::anyhow;
+
+use crate::{
+    arrange::{reachable, rects},
+    display_config::{apply_monitors_config, get_current_state::{self, LayoutMode}},
+};
+
+/// Logical sizes that are this close to an integer are considered integer, since scales are
+/// reported as single precision floats.
+const LOGICAL_SIZE_TOLERANCE: f64 = 0.01;
+
+fn name(logical_monitor: &apply_monitors_config::LogicalMonitor) -> String {
+    logical_monitor.monitors.iter()
+        .map(|monitor| monitor.connector.as_str())
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Checks planned config for problems that mutter would reject it for when applied with
+/// `layout_mode`, so that they could be reported per display instead of mutter's generic errors.
+pub fn layout_problems(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitors: &[apply_monitors_config::LogicalMonitor],
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if logical_monitors.is_empty() {
+        problems.push("at least one display should be enabled".to_string());
+        return problems;
+    }
+
+    let primary_count = logical_monitors.iter().filter(|logical_monitor| logical_monitor.primary).count();
+    if primary_count != 1 {
+        problems.push(format!("expected exactly one primary logical monitor, found {primary_count}"));
+    }
+
+    for logical_monitor in logical_monitors {
+        let name = name(logical_monitor);
+        if logical_monitor.monitors.is_empty() {
+            problems.push(format!("logical monitor at {},{}: does not have any displays", logical_monitor.x, logical_monitor.y));
+        }
+        for monitor in &logical_monitor.monitors {
+            let Some(display) = current_state.monitors.iter().find(|display| display.id.connector == monitor.connector) else {
+                problems.push(format!("{}: display is not connected", monitor.connector));
+                continue;
+            };
+            let Some(mode) = display.modes.iter().find(|mode| mode.id == monitor.mode) else {
+                problems.push(format!("{}: does not have a mode with \"{}\" ID", monitor.connector, monitor.mode));
+                continue;
+            };
+            if !mode.supported_scales.iter().any(|scale| (scale - logical_monitor.scale).abs() < f64::EPSILON) {
+                let supported = mode.supported_scales.iter().map(|scale| format!("{scale:.4}")).collect::<Vec<_>>().join(", ");
+                problems.push(format!("{}: scale {:.4} is not supported by {} mode, supported scales are {supported}", monitor.connector, logical_monitor.scale, mode.id));
+            } else if layout_mode == LayoutMode::Logical {
+                let width = mode.width as f64 / logical_monitor.scale;
+                let height = mode.height as f64 / logical_monitor.scale;
+                if (width - width.round()).abs() > LOGICAL_SIZE_TOLERANCE || (height - height.round()).abs() > LOGICAL_SIZE_TOLERANCE {
+                    problems.push(format!("{}: scale {:.4} results in a fractional logical size {width:.2}x{height:.2}", monitor.connector, logical_monitor.scale));
+                }
+            }
+        }
+        if logical_monitor.monitors.len() > 1 {
+            let modes: Vec<_> = logical_monitor.monitors.iter()
+                .filter_map(|monitor| current_state.monitors.iter()
+                    .find(|display| display.id.connector == monitor.connector)
+                    .and_then(|display| display.modes.iter().find(|mode| mode.id == monitor.mode)))
+                .map(|mode| (mode.width, mode.height))
+                .collect();
+            if modes.windows(2).any(|pair| pair[0] != pair[1]) {
+                problems.push(format!("{name}: mirrored displays should use modes with the same resolution"));
+            }
+        }
+    }
+
+    // Positions could only be checked when sizes are known
+    let Ok(rects) = rects(current_state, layout_mode, logical_monitors) else {
+        return problems;
+    };
+    let min_x = rects.iter().map(|rect| rect.x).min().unwrap_or_default();
+    let min_y = rects.iter().map(|rect| rect.y).min().unwrap_or_default();
+    if min_x != 0 || min_y != 0 {
+        problems.push(format!("layout should start at 0,0, but it starts at {min_x},{min_y}"));
+    }
+    for (index, rect) in rects.iter().enumerate() {
+        for (other_index, other) in rects.iter().enumerate().skip(index + 1) {
+            if rect.overlaps(other) {
+                problems.push(format!("{} overlaps {}", name(&logical_monitors[index]), name(&logical_monitors[other_index])));
+            }
+        }
+    }
+    let main_island = logical_monitors.iter().position(|logical_monitor| logical_monitor.primary).unwrap_or_default();
+    for (index, reached) in reachable(&rects, main_island).into_iter().enumerate() {
+        if !reached {
+            problems.push(format!("{}: not adjacent to {} or any display next to it", name(&logical_monitors[index]), name(&logical_monitors[main_island])));
+        }
+    }
+
+    let right = rects.iter().map(|rect| rect.x + rect.width).max().unwrap_or_default();
+    let bottom = rects.iter().map(|rect| rect.y + rect.height).max().unwrap_or_default();
+    for monitor in logical_monitors.iter().flat_map(|logical_monitor| &logical_monitor.monitors) {
+        let max_screen_size = current_state.monitors.iter()
+            .find(|display| display.id.connector == monitor.connector)
+            .and_then(|display| display.properties.max_screen_size);
+        if let Some((max_width, max_height)) = max_screen_size {
+            if right > max_width || bottom > max_height {
+                problems.push(format!("{}: layout size {right}x{bottom} exceeds maximum screen size {max_width}x{max_height}", monitor.connector));
+            }
+        }
+    }
+    problems
+}
+
+/// Fails with all problems found by [`layout_problems`], if there are any.
+pub fn validate_layout(
+    current_state: &get_current_state::Response,
+    layout_mode: LayoutMode,
+    logical_monitors: &[apply_monitors_config::LogicalMonitor],
+) -> anyhow::Result<()> {
+    let problems = layout_problems(current_state, layout_mode, logical_monitors);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("planned configuration is invalid:\n{}", problems.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{logical_monitor, monitor, state},
+        planner::current_config,
+    };
+
+    fn problems(current_state: &get_current_state::Response, layout_mode: LayoutMode) -> Vec<String> {
+        layout_problems(current_state, layout_mode, &current_config(current_state).unwrap())
+    }
+
+    #[test]
+    fn valid_layout_has_no_problems() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(3840, 2160)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"]), logical_monitor(1920, 0, 2.0, &["DP-1"])],
+        );
+        assert_eq!(problems(&current_state, LayoutMode::Logical), Vec::<String>::new());
+    }
+
+    #[test]
+    fn positions_are_checked_with_sizes_of_layout_mode() {
+        // Touching in logical layout mode, but overlapping in physical one
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(3840, 2160)])],
+            vec![logical_monitor(0, 0, 2.0, &["DP-1"]), logical_monitor(1920, 0, 1.0, &["eDP-1"])],
+        );
+        assert_eq!(problems(&current_state, LayoutMode::Logical), Vec::<String>::new());
+        assert_eq!(problems(&current_state, LayoutMode::Physical), [
+            "DP-1 overlaps eDP-1",
+            "eDP-1: not adjacent to DP-1 or any display next to it",
+        ]);
+    }
+
+    #[test]
+    fn gaps_and_offset_origin_are_reported() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(1920, 1080)])],
+            vec![logical_monitor(100, 0, 1.0, &["eDP-1"]), logical_monitor(2100, 0, 1.0, &["DP-1"])],
+        );
+        assert_eq!(problems(&current_state, LayoutMode::Logical), [
+            "layout should start at 0,0, but it starts at 100,0",
+            "DP-1: not adjacent to eDP-1 or any display next to it",
+        ]);
+    }
+
+    #[test]
+    fn fractional_logical_size_is_only_a_problem_in_logical_layout_mode() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(0, 0, 1.75, &["eDP-1"])],
+        );
+        assert_eq!(problems(&current_state, LayoutMode::Logical), ["eDP-1: scale 1.7500 results in a fractional logical size 1097.14x685.71"]);
+        assert_eq!(problems(&current_state, LayoutMode::Physical), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unsupported_scale_and_missing_primary_are_reported() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)])],
+            vec![logical_monitor(0, 0, 3.0, &["eDP-1"])],
+        );
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        logical_monitors[0].primary = false;
+        assert_eq!(layout_problems(&current_state, LayoutMode::Logical, &logical_monitors), [
+            "expected exactly one primary logical monitor, found 0",
+            "eDP-1: scale 3.0000 is not supported by 1920x1200@60.000 mode, supported scales are 1.0000, 1.2500, 1.5000, 1.7500, 2.0000",
+        ]);
+    }
+
+    #[test]
+    fn mirrored_displays_need_the_same_resolution() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1080)]), monitor("HDMI-1", &[(1920, 1080), (1280, 720)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1", "HDMI-1"])],
+        );
+        assert_eq!(problems(&current_state, LayoutMode::Logical), Vec::<String>::new());
+
+        let mut logical_monitors = current_config(&current_state).unwrap();
+        logical_monitors[0].monitors[1].mode = "1280x720@60.000".to_string();
+        assert_eq!(layout_problems(&current_state, LayoutMode::Logical, &logical_monitors), ["eDP-1+HDMI-1: mirrored displays should use modes with the same resolution"]);
+    }
+
+    #[test]
+    fn nothing_enabled_is_a_problem() {
+        let current_state = state(vec![monitor("eDP-1", &[(1920, 1200)])], Vec::new());
+        assert_eq!(layout_problems(&current_state, LayoutMode::Logical, &[]), ["at least one display should be enabled"]);
+    }
+}
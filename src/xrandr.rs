@@ -0,0 +1,459 @@
+use std::fmt::Write;
+
+use anyhow::anyhow;
+
+use crate::{
+    arrange::fix_layout,
+    display_config::{
+        apply_monitors_config,
+        get_current_state::{self, LogicalMonitorTransform, RefreshRateMode},
+    },
+    planner::{current_config, current_layout_mode, logical_size, DEFAULT_SCALE_TOLERANCE},
+};
+
+/// Subset of xrandr command line that could be translated into mutter config
+#[derive(Debug, Default)]
+pub struct XrandrRequest {
+    /// Print current state in xrandr format
+    pub query: bool,
+    /// Changes for each output, in order they were passed
+    pub outputs: Vec<OutputRequest>,
+}
+
+#[derive(Debug, Default)]
+pub struct OutputRequest {
+    pub connector: String,
+    pub mode: Option<(i32, i32)>,
+    pub rate: Option<f64>,
+    pub pos: Option<(i32, i32)>,
+    pub rotate: Option<LogicalMonitorTransform>,
+    pub primary: bool,
+    pub off: bool,
+    pub auto: bool,
+    pub relative: Option<(Relation, String)>,
+    /// xrandr framebuffer scale, 0.5 makes everything twice as big
+    pub scale: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    LeftOf,
+    RightOf,
+    Above,
+    Below,
+}
+
+impl XrandrRequest {
+    /// Parses xrandr arguments. Just like xrandr - options after `--output` apply to that output
+    pub fn parse(args: &[String]) -> anyhow::Result<Self> {
+        let mut request = XrandrRequest::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(anyhow!("{name} requires a value"));
+            match arg.as_str() {
+                "-q" | "--query" | "--current" => request.query = true,
+                "--output" => request.outputs.push(OutputRequest {
+                    connector: value(arg)?.clone(),
+                    ..Default::default()
+                }),
+                option => {
+                    let output = request.outputs.last_mut()
+                        .ok_or(anyhow!("{option} should be used after --output"))?;
+                    match option {
+                        "--mode" => output.mode = Some(parse_pair(value(option)?, 'x')?),
+                        "--rate" | "--refresh" => {
+                            let rate = value(option)?;
+                            output.rate = Some(rate.parse().map_err(|_| anyhow!("could not parse rate \"{rate}\""))?);
+                        },
+                        "--pos" => output.pos = Some(parse_pair(value(option)?, 'x')?),
+                        "--rotate" | "--rotation" => output.rotate = Some(match value(option)?.as_str() {
+                            "normal" => LogicalMonitorTransform::Normal,
+                            "left" => LogicalMonitorTransform::Turned90Deg,
+                            "inverted" => LogicalMonitorTransform::Turned180Deg,
+                            "right" => LogicalMonitorTransform::Turned270Deg,
+                            rotation => return Err(anyhow!("unknown rotation \"{rotation}\", expected one of normal, left, inverted, right")),
+                        }),
+                        "--primary" => output.primary = true,
+                        "--off" => output.off = true,
+                        "--auto" => output.auto = true,
+                        "--left-of" => output.relative = Some((Relation::LeftOf, value(option)?.clone())),
+                        "--right-of" => output.relative = Some((Relation::RightOf, value(option)?.clone())),
+                        "--above" => output.relative = Some((Relation::Above, value(option)?.clone())),
+                        "--below" => output.relative = Some((Relation::Below, value(option)?.clone())),
+                        "--scale" => {
+                            let scale = value(option)?;
+                            let (x, y) = match scale.split_once('x') {
+                                Some((x, y)) => (x, y),
+                                None => (scale.as_str(), scale.as_str()),
+                            };
+                            let (x, y): (f64, f64) = (
+                                x.parse().map_err(|_| anyhow!("could not parse scale \"{scale}\""))?,
+                                y.parse().map_err(|_| anyhow!("could not parse scale \"{scale}\""))?,
+                            );
+                            if x != y || x <= 0.0 {
+                                return Err(anyhow!("only uniform positive scale is supported, e.g. 0.5x0.5"));
+                            }
+                            output.scale = Some(x);
+                        },
+                        option => return Err(anyhow!("unsupported xrandr option \"{option}\"")),
+                    }
+                },
+            }
+        }
+        // Plain `xrandr` prints current state
+        if request.outputs.is_empty() {
+            request.query = true;
+        }
+        Ok(request)
+    }
+}
+
+fn parse_pair(s: &str, separator: char) -> anyhow::Result<(i32, i32)> {
+    s.split_once(separator)
+        .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)))
+        .ok_or(anyhow!("could not parse \"{s}\", expected format is <a>{separator}<b>"))
+}
+
+/// Builds a config for ApplyMonitorsConfig out of xrandr request. Relative positions are resolved
+/// after all modes, scales and rotations are applied, so that final sizes are used.
+pub fn plan_xrandr(current_state: &get_current_state::Response, request: &XrandrRequest) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+    let mut logical_monitors = current_config(current_state)?;
+
+    for output in &request.outputs {
+        let monitor = current_state.monitors.iter()
+            .find(|monitor| monitor.id.connector == output.connector)
+            .ok_or(anyhow!("could not find a display with \"{}\" connector name", output.connector))?;
+        let index = logical_monitors.iter()
+            .position(|logical_monitor| logical_monitor.monitors.iter().any(|m| m.connector == output.connector));
+
+        if output.off {
+            if let Some(index) = index {
+                logical_monitors[index].monitors.retain(|m| m.connector != output.connector);
+                if logical_monitors[index].monitors.is_empty() {
+                    logical_monitors.remove(index);
+                }
+            }
+            continue;
+        }
+
+        let index = match index {
+            Some(index) => index,
+            None if output.auto || output.mode.is_some() => {
+                logical_monitors.push(apply_monitors_config::LogicalMonitor {
+                    x: 0,
+                    y: 0,
+                    scale: 1.0,
+                    transform: LogicalMonitorTransform::Normal,
+                    primary: false,
+                    monitors: Vec::new(),
+                });
+                logical_monitors.len() - 1
+            },
+            None => return Err(anyhow!("display \"{}\" is disabled, use --auto or --mode to enable it", output.connector)),
+        };
+
+        let current_mode_id = logical_monitors[index].monitors.iter()
+            .find(|m| m.connector == output.connector)
+            .map(|m| m.mode.clone());
+        let current_mode = current_mode_id.as_ref()
+            .and_then(|id| monitor.modes.iter().find(|mode| &mode.id == id));
+        let mode = select_mode(monitor, current_mode, output)?;
+
+        let logical_monitor = &mut logical_monitors[index];
+        let enabling = logical_monitor.monitors.is_empty();
+        let new_monitor = apply_monitors_config::Monitor {
+            connector: monitor.id.connector.clone(),
+            mode: mode.id.clone(),
+            properties: apply_monitors_config::MonitorProperties {
+                underscanning: monitor.properties.is_underscanning,
+                color_mode: monitor.properties.supported_color_modes.as_ref().and(monitor.properties.color_mode),
+            },
+        };
+        logical_monitor.monitors.retain(|m| m.connector != output.connector);
+        logical_monitor.monitors.push(new_monitor);
+
+        // Scale is tied to a mode, so it has to be revalidated when mode changes
+        let wanted_scale = match output.scale {
+            Some(scale) => 1.0 / scale,
+            None if enabling => mode.preferred_scale,
+            None => logical_monitor.scale,
+        };
+        let scale = mode.supported_scales.iter()
+            .copied()
+            .min_by(|l, r| (l - wanted_scale).abs().total_cmp(&(r - wanted_scale).abs()))
+            .ok_or(anyhow!("display \"{}\" does not have any supported scales", output.connector))?;
+        // Current scale is just carried over, so only explicitly requested one has to be close
+        if output.scale.is_some() && (wanted_scale - scale).abs() > DEFAULT_SCALE_TOLERANCE {
+            return Err(anyhow!("display \"{}\" does not have any scale close to {:.0}%, closest one is {:.0}%", output.connector, wanted_scale * 100.0, scale * 100.0));
+        }
+        logical_monitor.scale = scale;
+
+        if let Some(transform) = output.rotate {
+            logical_monitor.transform = transform;
+        }
+        if let Some((x, y)) = output.pos {
+            logical_monitor.x = x;
+            logical_monitor.y = y;
+        }
+        if output.primary {
+            for (i, logical_monitor) in logical_monitors.iter_mut().enumerate() {
+                logical_monitor.primary = i == index;
+            }
+        }
+    }
+
+    for output in &request.outputs {
+        let Some((relation, other)) = &output.relative else {
+            continue;
+        };
+        let find = |connector: &str| logical_monitors.iter()
+            .position(|logical_monitor| logical_monitor.monitors.iter().any(|m| m.connector == connector))
+            .ok_or(anyhow!("display \"{}\" is not enabled", connector));
+        let index = find(&output.connector)?;
+        let other_index = find(other)?;
+        let layout_mode = current_layout_mode(current_state);
+        let (width, height) = logical_size(current_state, layout_mode, &logical_monitors[index])?;
+        let (other_width, other_height) = logical_size(current_state, layout_mode, &logical_monitors[other_index])?;
+        let (other_x, other_y) = (logical_monitors[other_index].x, logical_monitors[other_index].y);
+        let (x, y) = match relation {
+            Relation::LeftOf => (other_x - width, other_y),
+            Relation::RightOf => (other_x + other_width, other_y),
+            Relation::Above => (other_x, other_y - height),
+            Relation::Below => (other_x, other_y + other_height),
+        };
+        logical_monitors[index].x = x;
+        logical_monitors[index].y = y;
+    }
+
+    // Relative positioning could move layout away from the origin, which is fixed by repacking.
+    // Explicit positions are kept as they are, even if mutter would reject them
+    if request.outputs.iter().all(|output| output.pos.is_none()) {
+        fix_layout(current_state, current_layout_mode(current_state), &mut logical_monitors)?;
+    }
+    if !logical_monitors.is_empty() && !logical_monitors.iter().any(|logical_monitor| logical_monitor.primary) {
+        logical_monitors[0].primary = true;
+    }
+
+    Ok(logical_monitors)
+}
+
+/// Selects a mode the same way xrandr does: `--auto` without `--mode` picks preferred mode,
+/// resolution without rate keeps current rate if possible, otherwise uses preferred or highest.
+fn select_mode<'a>(monitor: &'a get_current_state::Monitor, current_mode: Option<&get_current_state::Mode>, output: &OutputRequest) -> anyhow::Result<&'a get_current_state::Mode> {
+    let preferred_mode = monitor.modes.iter().find(|mode| mode.properties.is_preferred.unwrap_or(false));
+    let (width, height) = match (output.mode, current_mode, output.auto) {
+        (Some(resolution), _, _) => resolution,
+        (None, Some(mode), false) => (mode.width, mode.height),
+        _ => preferred_mode
+            .map(|mode| (mode.width, mode.height))
+            .ok_or(anyhow!("display \"{}\" does not have a preferred mode", output.connector))?,
+    };
+    let mut candidates: Vec<_> = monitor.modes.iter()
+        .filter(|mode| mode.width == width && mode.height == height)
+        .collect();
+    if candidates.is_empty() {
+        return Err(anyhow!("display \"{}\" does not support {}x{}", output.connector, width, height));
+    }
+    // xrandr does not know about VRR, so keep whatever is used currently when possible
+    let refresh_rate_mode = current_mode.and_then(|mode| mode.properties.refresh_rate_mode).unwrap_or_default();
+    candidates.sort_by_key(|mode| mode.properties.refresh_rate_mode.unwrap_or_default() != refresh_rate_mode);
+
+    let target_rate = match output.rate {
+        Some(rate) => Some(rate),
+        None => current_mode
+            .filter(|mode| mode.width == width && mode.height == height)
+            .map(|mode| mode.refresh_rate),
+    };
+    let mode = match target_rate {
+        Some(rate) => candidates.iter()
+            .min_by(|l, r| (l.refresh_rate - rate).abs().total_cmp(&(r.refresh_rate - rate).abs())),
+        None => candidates.iter()
+            .find(|mode| mode.properties.is_preferred.unwrap_or(false))
+            .or(candidates.iter().max_by(|l, r| l.refresh_rate.total_cmp(&r.refresh_rate))),
+    };
+    Ok(*mode.expect("candidates are not empty"))
+}
+
+/// Formats current state similar to `xrandr --query`
+pub fn format_query(current_state: &get_current_state::Response) -> anyhow::Result<String> {
+    let config = current_config(current_state)?;
+    let mut width = 0;
+    let mut height = 0;
+    for logical_monitor in &config {
+        let (w, h) = logical_size(current_state, current_layout_mode(current_state), logical_monitor)?;
+        width = width.max(logical_monitor.x + w);
+        height = height.max(logical_monitor.y + h);
+    }
+    let (max_width, max_height) = current_state.monitors.iter()
+        .find_map(|monitor| monitor.properties.max_screen_size)
+        .unwrap_or((width, height));
+
+    let mut out = String::new();
+    writeln!(out, "Screen 0: minimum 0 x 0, current {width} x {height}, maximum {max_width} x {max_height}")?;
+    for monitor in &current_state.monitors {
+        let logical_monitor = current_state.logical_monitors.iter()
+            .find(|logical_monitor| logical_monitor.monitors.contains(&monitor.id));
+        let current_mode = monitor.modes.iter().find(|mode| mode.properties.is_current.unwrap_or(false));
+
+        write!(out, "{} connected", monitor.id.connector)?;
+        if let (Some(logical_monitor), Some(mode)) = (logical_monitor, current_mode) {
+            if logical_monitor.primary {
+                write!(out, " primary")?;
+            }
+            let rotated = logical_monitor.transform as u32 % 2 == 1;
+            let (w, h) = if rotated { (mode.height, mode.width) } else { (mode.width, mode.height) };
+            write!(out, " {}x{}+{}+{}", w, h, logical_monitor.x, logical_monitor.y)?;
+            match logical_monitor.transform as u32 % 4 {
+                1 => write!(out, " left")?,
+                2 => write!(out, " inverted")?,
+                3 => write!(out, " right")?,
+                _ => {},
+            }
+        }
+        write!(out, " (normal left inverted right)")?;
+        if let (Some(width_mm), Some(height_mm)) = (monitor.properties.width_mm, monitor.properties.height_mm) {
+            write!(out, " {width_mm}mm x {height_mm}mm")?;
+        }
+        writeln!(out)?;
+
+        let mut modes: Vec<_> = monitor.modes.iter()
+            .filter(|mode| mode.properties.refresh_rate_mode.unwrap_or_default() == RefreshRateMode::Fixed)
+            .cloned()
+            .collect();
+        modes.sort();
+        modes.reverse();
+        for resolution_modes in modes.chunk_by(|a, b| a.width == b.width && a.height == b.height) {
+            write!(out, "   {:<12}", format!("{}x{}", resolution_modes[0].width, resolution_modes[0].height))?;
+            for mode in resolution_modes {
+                // Current mode could be a variable counterpart of the listed one
+                let is_current = monitor.modes.iter().any(|m| {
+                    m.properties.is_current.unwrap_or(false) && m.width == mode.width && m.height == mode.height && m.refresh_rate == mode.refresh_rate
+                });
+                let current = if is_current { '*' } else { ' ' };
+                let preferred = if mode.properties.is_preferred.unwrap_or(false) { '+' } else { ' ' };
+                write!(out, " {:6.2}{}{}", mode.refresh_rate, current, preferred)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{logical_monitor, monitor, state};
+
+    fn parse(args: &str) -> anyhow::Result<XrandrRequest> {
+        XrandrRequest::parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn plan(current_state: &get_current_state::Response, args: &str) -> anyhow::Result<Vec<apply_monitors_config::LogicalMonitor>> {
+        plan_xrandr(current_state, &parse(args)?)
+    }
+
+    /// Connectors, positions and modes of planned logical monitors, with primary one marked by *
+    fn summary(logical_monitors: &[apply_monitors_config::LogicalMonitor]) -> Vec<String> {
+        logical_monitors.iter().map(|logical_monitor| {
+            let monitors: Vec<_> = logical_monitor.monitors.iter().map(|monitor| format!("{} {}", monitor.connector, monitor.mode)).collect();
+            let primary = if logical_monitor.primary { "*" } else { "" };
+            format!("{}{primary} at {},{}", monitors.join(" + "), logical_monitor.x, logical_monitor.y)
+        }).collect()
+    }
+
+    fn laptop_with_two_displays() -> get_current_state::Response {
+        state(
+            vec![
+                monitor("eDP-1", &[(1920, 1200)]),
+                monitor("DP-1", &[(2560, 1440), (1920, 1080)]),
+                monitor("HDMI-1", &[(1920, 1080)]),
+            ],
+            vec![
+                logical_monitor(0, 0, 1.0, &["eDP-1"]),
+                logical_monitor(1920, 0, 1.0, &["DP-1"]),
+                logical_monitor(4480, 0, 1.0, &["HDMI-1"]),
+            ],
+        )
+    }
+
+    #[test]
+    fn options_apply_to_the_last_output() {
+        let request = parse("--output DP-1 --mode 1920x1080 --rate 59.94 --left-of eDP-1 --primary --output HDMI-1 --off").unwrap();
+        assert!(!request.query);
+        assert_eq!(request.outputs.len(), 2);
+        let dp = &request.outputs[0];
+        assert_eq!((dp.connector.as_str(), dp.mode, dp.rate, dp.primary), ("DP-1", Some((1920, 1080)), Some(59.94), true));
+        assert_eq!(dp.relative, Some((Relation::LeftOf, "eDP-1".to_string())));
+        assert!(request.outputs[1].off);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert!(parse("--mode 1920x1080").is_err());
+        assert!(parse("--output DP-1 --mode 1920").is_err());
+        assert!(parse("--output DP-1 --rotate sideways").is_err());
+        assert!(parse("--output DP-1 --scale 0.5x1").is_err());
+        assert!(parse("--output DP-1 --brightness 0.5").is_err());
+        assert!(parse("--output").is_err());
+        assert!(parse("").unwrap().query);
+    }
+
+    #[test]
+    fn left_of_with_another_output_turned_off() {
+        let current_state = laptop_with_two_displays();
+        let logical_monitors = plan(&current_state, "--output HDMI-1 --off --output DP-1 --mode 1920x1080 --left-of eDP-1").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1200@60.000* at 1920,0", "DP-1 1920x1080@60.000 at 0,0"]);
+    }
+
+    #[test]
+    fn below_uses_size_after_scale_change() {
+        let current_state = laptop_with_two_displays();
+        let logical_monitors = plan(&current_state, "--output HDMI-1 --off --output DP-1 --scale 0.5 --output eDP-1 --below DP-1").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1200@60.000* at 0,720", "DP-1 2560x1440@60.000 at 0,0"]);
+        assert_eq!(logical_monitors[1].scale, 2.0);
+    }
+
+    #[test]
+    fn scale_has_to_be_close_to_a_supported_one() {
+        let current_state = laptop_with_two_displays();
+        let logical_monitors = plan(&current_state, "--output DP-1 --scale 0.67").unwrap();
+        assert_eq!(logical_monitors[1].scale, 1.5);
+        let err = plan(&current_state, "--output DP-1 --scale 0.75").unwrap_err();
+        assert_eq!(err.to_string(), "display \"DP-1\" does not have any scale close to 133%, closest one is 125%");
+    }
+
+    #[test]
+    fn turning_off_middle_display_closes_the_gap() {
+        let current_state = laptop_with_two_displays();
+        let logical_monitors = plan(&current_state, "--output DP-1 --off").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1200@60.000* at 0,0", "HDMI-1 1920x1080@60.000 at 1920,0"]);
+    }
+
+    #[test]
+    fn explicit_positions_are_kept() {
+        let current_state = laptop_with_two_displays();
+        let logical_monitors = plan(&current_state, "--output DP-1 --off --output HDMI-1 --pos 3000x0").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1200@60.000* at 0,0", "HDMI-1 1920x1080@60.000 at 3000,0"]);
+    }
+
+    #[test]
+    fn turning_off_mirrored_display_keeps_the_other_one() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1080)]), monitor("HDMI-1", &[(1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1", "HDMI-1"])],
+        );
+        let logical_monitors = plan(&current_state, "--output HDMI-1 --off").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1080@60.000* at 0,0"]);
+    }
+
+    #[test]
+    fn disabled_display_is_enabled_with_auto() {
+        let current_state = state(
+            vec![monitor("eDP-1", &[(1920, 1200)]), monitor("DP-1", &[(2560, 1440), (1920, 1080)])],
+            vec![logical_monitor(0, 0, 1.0, &["eDP-1"])],
+        );
+        assert!(plan(&current_state, "--output DP-1 --primary").is_err());
+        let logical_monitors = plan(&current_state, "--output DP-1 --auto --right-of eDP-1 --primary").unwrap();
+        assert_eq!(summary(&logical_monitors), ["eDP-1 1920x1200@60.000 at 0,0", "DP-1 2560x1440@60.000* at 1920,0"]);
+    }
+}